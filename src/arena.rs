@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ball::Ball;
+use crate::motion::{Axis, time_to_distance};
+use crate::obstacle::Obstacle;
+use crate::simulation::{CurvedCollision, search_collision};
+
+
+/// The shape of the box the balls bounce around in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arena {
+    /// The whole `width`×`height` rectangle, with four walls
+    #[default]
+    Rectangle,
+    /// The biggest circle that fits into the rectangle, in its center
+    Circle,
+    /// The ellipse that fills the rectangle
+    Ellipse,
+    /// The whole rectangle without walls, with opposite edges joined, so that balls leaving it on one side come back on the other
+    Periodic,
+}
+
+impl Arena {
+    /// Whether a ball with radius `r` at (x, y) is inside the arena of a `width`×`height` box (touching its edge counts as inside).
+    /// In a periodic arena every ball that is small enough not to touch its own copies is, wherever it is.
+    pub fn contains(&self, x: f64, y: f64, r: f64, width: f64, height: f64) -> bool {
+        match self.ellipse(width, height) {
+            None if *self == Self::Periodic => 2.0 * r + 1.0 <= width.min(height),
+            None => x >= r && y >= r && x <= width - r - 1.0 && y <= height - r - 1.0,
+            Some(ellipse) => ellipse.distance(x, y) >= r,
+        }
+    }
+
+    // the shifts by which everything in a periodic arena is repeated across its edges, starting with no shift
+    // (which is the only one for other arenas)
+    pub(crate) fn shifts(&self, width: f64, height: f64) -> Vec<[f64; 2]> {
+        if *self != Self::Periodic {
+            return vec![[0.0, 0.0]];
+        }
+        let mut shifts = vec![[0.0, 0.0]];
+        for x in [-width, 0.0, width] {
+            for y in [-height, 0.0, height] {
+                if x != 0.0 || y != 0.0 {
+                    shifts.push([x, y]);
+                }
+            }
+        }
+        shifts
+    }
+
+    // the shift that moves a point at `from` to its copy that is nearest to `to` in a periodic arena (no shift in other arenas)
+    pub(crate) fn shift_towards(&self, from: [f64; 2], to: [f64; 2], width: f64, height: f64) -> [f64; 2] {
+        if *self != Self::Periodic {
+            return [0.0, 0.0];
+        }
+        [((to[0] - from[0]) / width).round() * width, ((to[1] - from[1]) / height).round() * height]
+    }
+
+    // moves a ball outside of a periodic arena to the same place inside it (nothing happens in other arenas)
+    pub(crate) fn wrap(&self, ball: &mut Ball, width: f64, height: f64) {
+        if *self == Self::Periodic {
+            ball.x = ball.x.rem_euclid(width);
+            ball.y = ball.y.rem_euclid(height);
+        }
+    }
+
+    // whether two balls overlap, in a periodic arena also across the edges
+    pub(crate) fn balls_overlap(&self, ball1: &Ball, ball2: &Ball, width: f64, height: f64) -> bool {
+        ball1.overlaps(&ball2.shifted(self.shift_towards([ball2.x, ball2.y], [ball1.x, ball1.y], width, height)))
+    }
+
+    // whether a ball overlaps an obstacle, in a periodic arena also across the edges
+    pub(crate) fn overlaps_obstacle(&self, ball: &Ball, obstacle: &Obstacle, width: f64, height: f64) -> bool {
+        self.shifts(width, height).into_iter().any(|shift| obstacle.overlaps(&ball.shifted(shift)))
+    }
+
+    // the round edge of the arena (None for the rectangle and periodic arenas),
+    // like the walls of the rectangle it goes through the centers of the pixels on the edges of the image
+    pub(crate) fn ellipse(&self, width: f64, height: f64) -> Option<Ellipse> {
+        let center = [(width - 1.0) / 2.0, (height - 1.0) / 2.0];
+        match self {
+            Self::Rectangle | Self::Periodic => None,
+            Self::Circle => Some(Ellipse { center, semi_axes: [center[0].min(center[1]); 2] }),
+            Self::Ellipse => Some(Ellipse { center, semi_axes: center }),
+        }
+    }
+}
+
+/// An ellipse with its axes along the x and y axes that balls bounce around in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Ellipse {
+    pub center: [f64; 2],
+    pub semi_axes: [f64; 2],
+}
+
+impl Ellipse {
+    fn is_circle(&self) -> bool {
+        self.semi_axes[0] == self.semi_axes[1]
+    }
+
+    // the point of the edge that is closest to (x, y), relative to the center
+    fn closest(&self, x: f64, y: f64) -> [f64; 2] {
+        let (x, y) = (x - self.center[0], y - self.center[1]);
+        if self.is_circle() {
+            let distance = x.hypot(y);
+            let radius = self.semi_axes[0];
+            return if distance > 0.0 { [x * radius / distance, y * radius / distance] } else { [radius, 0.0] };
+        }
+        // the ellipse is symmetric, so it is enough to solve this for the first quadrant with the longer axis first
+        let swapped = self.semi_axes[0] < self.semi_axes[1];
+        let ([a, b], [p, q]) = if swapped { ([self.semi_axes[1], self.semi_axes[0]], [y.abs(), x.abs()]) } else { (self.semi_axes, [x.abs(), y.abs()]) };
+        let [closest_p, closest_q] = closest_in_quadrant(a, b, p, q);
+        let closest = [closest_p.copysign(if swapped { y } else { x }), closest_q.copysign(if swapped { x } else { y })];
+        if swapped { [closest[1], closest[0]] } else { closest }
+    }
+
+    // the distance of (x, y) from the edge (negative outside)
+    fn distance(&self, x: f64, y: f64) -> f64 {
+        let closest = self.closest(x, y);
+        let (x, y) = (x - self.center[0], y - self.center[1]);
+        let distance = (x - closest[0]).hypot(y - closest[1]);
+        let [a, b] = self.semi_axes;
+        if (x / a).powi(2) + (y / b).powi(2) <= 1.0 { distance } else { -distance }
+    }
+
+    /// The distance between the ball and the edge (negative if it sticks out).
+    pub fn gap(&self, ball: &Ball) -> f64 {
+        self.distance(ball.x, ball.y) - ball.r
+    }
+
+    /// The direction from the edge towards the inside at the point of the edge closest to the ball (a unit vector).
+    pub fn normal(&self, ball: &Ball) -> [f64; 2] {
+        let closest = self.closest(ball.x, ball.y);
+        let [a, b] = self.semi_axes;
+        let (nx, ny) = (-closest[0] / (a * a), -closest[1] / (b * b));
+        let length = nx.hypot(ny);
+        [nx / length, ny / length]
+    }
+
+    /// Returns the time after which the ball will hit the edge, if it ever does.
+    /// For a circle without drag it is exact, otherwise the search is stopped after `max_steps`.
+    pub fn time_to_hit(&self, ball: &Ball, axes: [Axis; 2], max_steps: usize) -> Option<CurvedCollision> {
+        if self.is_circle() && !axes.iter().any(Axis::has_drag) {
+            let position = [ball.x - self.center[0], ball.y - self.center[1]];
+            let gravity = [axes[0].acceleration, axes[1].acceleration];
+            return time_to_distance(position, [ball.v_x, ball.v_y], gravity, self.semi_axes[0] - ball.r, false).map(CurvedCollision::At);
+        }
+        self.search(ball, axes, max_steps)
+    }
+
+    // the collision time of a ball whose path is curved by drag or that moves inside an ellipse
+    fn search(&self, ball: &Ball, axes: [Axis; 2], max_steps: usize) -> Option<CurvedCollision> {
+        let tolerance = self.semi_axes[0].min(self.semi_axes[1]) * 1e-9;
+        // along each axis the velocity approaches the terminal velocity, so the acceleration never gets bigger,
+        // without drag the acceleration is constant
+        let max_acceleration = axes[0].acceleration_at(ball.v_x).hypot(axes[1].acceleration_at(ball.v_y));
+        let max_speed = if axes.iter().any(Axis::has_drag) {
+            axes[0].terminal_speed().abs().max(ball.v_x.abs()).hypot(axes[1].terminal_speed().abs().max(ball.v_y.abs()))
+        } else {
+            f64::INFINITY
+        };
+        // for a circle the gap is compared through (R^2 - d^2) / 2R, which is 0 at the same time, but changes its rate at most this fast
+        let radius = self.semi_axes[0] - ball.r;
+        let max_curvature = (max_speed * max_speed + self.semi_axes[0] * max_acceleration) / radius;
+
+        search_collision(ball, axes, max_steps, tolerance, true, |moved, _| (self.gap(moved), self.normal(moved), [0.0, 0.0]), |moved, gap, _| {
+            if self.is_circle() && max_curvature.is_finite() {
+                let (x, y) = (moved.x - self.center[0], moved.y - self.center[1]);
+                let proxy = ((radius * radius - x * x - y * y) / (2.0 * radius)).max(tolerance);
+                let proxy_speed = -(x * moved.v_x + y * moved.v_y) / radius;
+                2.0 * proxy / (-proxy_speed + (proxy_speed * proxy_speed + 2.0 * max_curvature * proxy).sqrt())
+            } else {
+                // the gap can't shrink faster than the ball moves, and the speed grows at most with the acceleration
+                let speed = moved.v_x.hypot(moved.v_y);
+                2.0 * gap / (speed + (speed * speed + 2.0 * max_acceleration * gap).sqrt())
+            }
+        })
+    }
+}
+
+// the point of the ellipse with semi-axes a >= b closest to (p, q) (both not negative),
+// after D. Eberly, "Distance from a Point to an Ellipse, an Ellipsoid, or a Hyperellipsoid"
+fn closest_in_quadrant(a: f64, b: f64, p: f64, q: f64) -> [f64; 2] {
+    if q > 0.0 {
+        if p > 0.0 {
+            let (z0, z1) = (p / a, q / b);
+            let g = z0 * z0 + z1 * z1 - 1.0;
+            if g == 0.0 {
+                return [p, q];
+            }
+            // the closest point is (r0 p / (u + r0 - 1), q / u) for the root u of this function (s + 1 in the paper), found by bisection,
+            // it is tiny for points close to the longer axis, so it has to be precise relative to its size
+            let r0 = (a / b).powi(2);
+            let r0_minus_1 = (a - b) * (a + b) / (b * b);
+            let n0 = r0 * z0;
+            let (mut u0, mut u1) = (z1, if g < 0.0 { 1.0 } else { n0.hypot(z1) });
+            let mut u = u0;
+            while u1 - u0 > f64::EPSILON * u1 {
+                u = u0 + (u1 - u0) / 2.0;
+                let value = (n0 / (u + r0_minus_1)).powi(2) + (z1 / u).powi(2) - 1.0;
+                if value > 0.0 {
+                    u0 = u;
+                } else if value < 0.0 {
+                    u1 = u;
+                } else {
+                    break;
+                }
+            }
+            [r0 * p / (u + r0_minus_1), q / u]
+        } else {
+            [0.0, b]
+        }
+    } else {
+        // on the longer axis, the closest point is off the axis if the point is closer to the center than the center of curvature of the end
+        let numerator = a * p;
+        let denominator = a * a - b * b;
+        if numerator < denominator {
+            let share = numerator / denominator;
+            [a * share, b * (1.0 - share * share).sqrt()]
+        } else {
+            [a, 0.0]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the distance of (x, y) from the closest of many points along the edge, refined around the closest one
+    fn brute_force_distance(ellipse: &Ellipse, x: f64, y: f64) -> f64 {
+        let [a, b] = ellipse.semi_axes;
+        let (x, y) = (x - ellipse.center[0], y - ellipse.center[1]);
+        let distance = |angle: f64| (a * angle.cos() - x).hypot(b * angle.sin() - y);
+        let steps = 10_000;
+        let step = std::f64::consts::TAU / steps as f64;
+        let mut best = (0..steps).map(|i| i as f64 * step).min_by(|&u, &v| distance(u).total_cmp(&distance(v))).unwrap();
+        let mut width = step;
+        while width > 1e-12 {
+            best = [best - width / 2.0, best, best + width / 2.0].into_iter().min_by(|&u, &v| distance(u).total_cmp(&distance(v))).unwrap();
+            width /= 2.0;
+        }
+        distance(best)
+    }
+
+    #[test]
+    fn finds_the_closest_point_of_an_ellipse() {
+        for semi_axes in [[300.0, 100.0], [50.0, 400.0], [200.0, 199.0], [120.0, 120.0]] {
+            let ellipse = Ellipse { center: [400.0, 300.0], semi_axes };
+            for i in -12..=12 {
+                for j in -12..=12 {
+                    let (x, y) = (400.0 + 35.0 * i as f64, 300.0 + 37.0 * j as f64);
+                    let closest = ellipse.closest(x, y);
+                    let [a, b] = semi_axes;
+                    assert!(((closest[0] / a).powi(2) + (closest[1] / b).powi(2) - 1.0).abs() < 1e-12, "{closest:?} isn't on the edge");
+                    let distance = (x - 400.0 - closest[0]).hypot(y - 300.0 - closest[1]);
+                    let expected = brute_force_distance(&ellipse, x, y);
+                    assert!((distance - expected).abs() < 1e-7 * expected.max(1.0), "{semi_axes:?} ({x}, {y}): {distance} != {expected}");
+                    // inside it is positive
+                    let inside = ((x - 400.0) / a).powi(2) + ((y - 300.0) / b).powi(2) < 1.0;
+                    assert_eq!(ellipse.distance(x, y) > 0.0, inside && distance > 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn finds_the_closest_point_near_the_axes() {
+        let ellipse = Ellipse { center: [0.0, 0.0], semi_axes: [300.0, 100.0] };
+        // the center of curvature of the ends of the longer axis is at 300 - 100^2 / 300
+        for (x, y) in [(0.0, 0.0), (266.0, 0.0), (267.0, 0.0), (500.0, 0.0), (0.0, 50.0), (0.0, -250.0), (266.0, 1e-9), (-100.0, -1e-12)] {
+            let closest = ellipse.closest(x, y);
+            let distance = (x - closest[0]).hypot(y - closest[1]);
+            let expected = brute_force_distance(&ellipse, x, y);
+            assert!((distance - expected).abs() < 1e-7 * expected.max(1.0), "({x}, {y}): {distance} != {expected}");
+        }
+    }
+}
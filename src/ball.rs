@@ -0,0 +1,117 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+
+/// The way the mass of a ball is derived from its radius.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MassModel {
+    /// Mass is proportional to the area of the circle.
+    Circle,
+    /// Mass is proportional to the volume of the sphere.
+    Ball,
+}
+
+impl MassModel {
+    /// Returns the mass of a ball with the given radius.
+    pub fn mass(&self, radius: f64) -> f64 {
+        match self {
+            Self::Circle => radius * radius * PI,
+            Self::Ball => (radius * radius * radius * PI * 4.0) / 3.0,
+        }
+    }
+
+    /// Returns the radius of a ball with the given mass (the inverse of [MassModel::mass]).
+    pub fn radius(&self, mass: f64) -> f64 {
+        match self {
+            Self::Circle => (mass / PI).sqrt(),
+            Self::Ball => (3.0 * mass / (4.0 * PI)).cbrt(),
+        }
+    }
+
+    /// Returns the moment of inertia of a ball with the given mass and radius (a disc for circles, a solid sphere for balls).
+    pub fn inertia(&self, mass: f64, radius: f64) -> f64 {
+        match self {
+            Self::Circle => mass * radius * radius / 2.0,
+            Self::Ball => 2.0 * mass * radius * radius / 5.0,
+        }
+    }
+}
+
+/// A single ball in the simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ball {
+    /// The x coordinate of the center
+    pub x: f64,
+    /// The y coordinate of the center
+    pub y: f64,
+    /// The mass
+    pub m: f64,
+    /// The radius
+    pub r: f64,
+    /// The velocity along the x axis
+    pub v_x: f64,
+    /// The velocity along the y axis
+    pub v_y: f64,
+    /// The moment of inertia
+    pub inertia: f64,
+    /// The angle by which the ball is rotated (counterclockwise, in radians)
+    pub angle: f64,
+    /// The angular velocity (counterclockwise, in radians per second)
+    pub omega: f64,
+    /// The electric charge, which only matters for the forces between balls ([Interactions](crate::Interactions))
+    pub charge: f64,
+    /// The fill color
+    pub color: [u8; 3],
+    /// The color of the outline
+    pub outline_color: [u8; 3],
+    /// The width of the outline, drawn inside the edge of the ball (0 for no outline)
+    pub outline_width: f64,
+    /// Whether the inside of the ball is filled with `color` (otherwise only the outline is drawn)
+    pub filled: bool,
+    /// The opacity (0 is invisible, 1 is solid), translucent balls blend with whatever is behind them
+    pub opacity: f64,
+    /// The coefficient of restitution of this ball's collisions (both with balls and walls), the global one is used if not set
+    pub restitution: Option<f64>,
+    /// The color of a spoke from the center to the edge that shows the rotation (no spoke if not set)
+    pub spoke_color: Option<[u8; 3]>,
+}
+
+impl Ball {
+    /// Creates a solid filled ball without an outline, with the moment of inertia of a disc, without rotation and without charge.
+    pub fn new(x: f64, y: f64, mass: f64, radius: f64, velocity_x: f64, velocity_y: f64, color: [u8; 3]) -> Self {
+        Self {
+            x,
+            y,
+            m: mass,
+            r: radius,
+            v_x: velocity_x,
+            v_y: velocity_y,
+            inertia: MassModel::Circle.inertia(mass, radius),
+            angle: 0.0,
+            omega: 0.0,
+            charge: 0.0,
+            color,
+            outline_color: color,
+            outline_width: 0.0,
+            filled: true,
+            opacity: 1.0,
+            restitution: None,
+            spoke_color: None,
+        }
+    }
+
+    /// Whether this ball and `other` overlap (just touching doesn't count).
+    pub fn overlaps(&self, other: &Ball) -> bool {
+        (self.x - other.x).powi(2) + (self.y - other.y).powi(2) < (self.r + other.r).powi(2)
+    }
+
+    // a copy of the ball moved by `shift`
+    pub(crate) fn shifted(&self, shift: [f64; 2]) -> Ball {
+        let mut ball = self.clone();
+        ball.x += shift[0];
+        ball.y += shift[1];
+        ball
+    }
+}
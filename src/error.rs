@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+
+
+/// Everything that can go wrong while setting up, running or saving a simulation.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid settings or input (e.g. zero FPS, invalid scene file)
+    Validation(String),
+    /// The balls can't be placed in the given area
+    Placement(String),
+    /// Reading or writing a file failed
+    Io {
+        /// What was being done when the error happened
+        context: String,
+        /// The underlying error
+        source: io::Error,
+    },
+    /// The encoder (ffmpeg) is missing or failed
+    Encoder(String),
+}
+
+impl Error {
+    /// The exit code the process should end with because of this error.
+    /// `2` is used for invalid input, the same as for invalid command line arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Validation(_) => 2,
+            Self::Placement(_) => 3,
+            Self::Io { .. } => 4,
+            Self::Encoder(_) => 5,
+        }
+    }
+
+    /// Creates an [Error::Io] describing what was being done when `source` happened.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Self {
+        Self::Io { context: context.into(), source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Validation(message) | Self::Placement(message) | Self::Encoder(message) => write!(f, "{}", message),
+            Self::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_errors_to_exit_codes() {
+        assert_eq!(Error::Validation(String::from("FPS must be greater than 0")).exit_code(), 2);
+        assert_eq!(Error::Placement(String::from("Can't fit all balls in the given area")).exit_code(), 3);
+        assert_eq!(Error::io("Can't read scene file", io::Error::from(io::ErrorKind::NotFound)).exit_code(), 4);
+        assert_eq!(Error::Encoder(String::from("ffmpeg failed")).exit_code(), 5);
+    }
+
+    #[test]
+    fn describes_io_errors_with_their_context() {
+        let error = Error::io("Can't read scene file a.toml", io::Error::other("disk on fire"));
+        assert_eq!(error.to_string(), "Can't read scene file a.toml: disk on fire");
+        assert!(std::error::Error::source(&error).is_some());
+        assert!(std::error::Error::source(&Error::Encoder(String::new())).is_none());
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+
+/// What happens at an event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    /// Two balls collide
+    Balls(usize, usize),
+    /// A ball hits a wall (0: left, 1: right, 2: bottom, 3: top, or 0 for the edge of a round arena)
+    Wall(usize, usize),
+    /// A ball moves into a neighbouring grid cell across a boundary (0: left, 1: right, 2: bottom, 3: top)
+    Cell(usize, usize),
+    /// Two balls get as close as they surely can without colliding, so their collision has to be predicted further
+    Approach(usize, usize),
+    /// A ball gets as close to the edge of a round arena as it surely can without colliding, so its collision has to be predicted further
+    WallApproach(usize),
+    /// A ball hits a part of an obstacle
+    Obstacle(usize, usize),
+    /// A ball gets as close to a part of an obstacle as it surely can without colliding, so their collision has to be predicted further
+    ObstacleApproach(usize, usize),
+}
+
+impl EventKind {
+    fn balls(&self) -> [Option<usize>; 2] {
+        match *self {
+            Self::Balls(ball1, ball2) | Self::Approach(ball1, ball2) => [Some(ball1), Some(ball2)],
+            Self::Wall(ball, _) | Self::Cell(ball, _) | Self::Obstacle(ball, _) | Self::ObstacleApproach(ball, _) | Self::WallApproach(ball) => [Some(ball), None],
+        }
+    }
+}
+
+/// A predicted event at an absolute simulation time.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Event {
+    pub time: f64,
+    pub kind: EventKind,
+    // counts of the involved balls when the event was predicted
+    counts: [u64; 2],
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, so that BinaryHeap (a max-heap) pops the earliest event first
+        other.time.total_cmp(&self.time)
+    }
+}
+
+/// A priority queue of predicted events.
+/// Instead of removing events when a ball changes its course, every ball has a counter that is changed,
+/// and events predicted with an old counter are skipped when they come up.
+pub(crate) struct EventQueue {
+    heap: BinaryHeap<Event>,
+    counts: Vec<u64>,
+    // the next value of a counter, values are never reused, so events of removed balls can't become valid again
+    next_count: u64,
+    // the size of the heap at which outdated events are dropped
+    compact_at: usize,
+}
+
+impl EventQueue {
+    pub fn new(num_of_balls: usize) -> Self {
+        Self { heap: BinaryHeap::new(), counts: vec![0; num_of_balls], next_count: 1, compact_at: 16 * (num_of_balls + 64) }
+    }
+
+    /// Adds an event predicted with the current state of the involved balls.
+    pub fn push(&mut self, time: f64, kind: EventKind) {
+        let counts = kind.balls().map(|ball| ball.map_or(0, |ball| self.counts[ball]));
+        self.heap.push(Event { time, kind, counts });
+
+        // drop outdated events if too many of them pile up
+        if self.heap.len() > self.compact_at {
+            let counts = &self.counts;
+            self.heap.retain(|event| Self::is_valid(counts, event));
+            self.compact_at = (2 * self.heap.len()).max(16 * (self.counts.len() + 64));
+        }
+    }
+
+    /// Removes and returns the earliest valid event if it happens at or before `until`.
+    pub fn pop_until(&mut self, until: f64) -> Option<Event> {
+        while let Some(event) = self.heap.peek() {
+            if !Self::is_valid(&self.counts, event) {
+                self.heap.pop();
+            } else if event.time <= until {
+                return self.heap.pop();
+            } else {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Marks all events of `ball` predicted so far as outdated.
+    pub fn invalidate(&mut self, ball: usize) {
+        self.counts[ball] = self.next_count;
+        self.next_count += 1;
+    }
+
+    /// Adds a ball after the last one.
+    pub fn insert(&mut self) {
+        self.counts.push(self.next_count);
+        self.next_count += 1;
+    }
+
+    /// Marks all events of `ball` as outdated and removes it, the last ball takes its place
+    /// (its events are outdated too, as they were predicted with the old index).
+    pub fn remove(&mut self, ball: usize) {
+        self.counts.swap_remove(ball);
+        if ball < self.counts.len() {
+            self.invalidate(ball);
+        }
+    }
+
+    fn is_valid(counts: &[u64], event: &Event) -> bool {
+        event.kind.balls().iter().zip(event.counts).all(|(ball, count)| ball.is_none_or(|ball| counts.get(ball) == Some(&count)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(queue: &mut EventQueue) -> Vec<EventKind> {
+        std::iter::from_fn(|| queue.pop_until(f64::INFINITY)).map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn pops_in_order_until_the_limit() {
+        let mut queue = EventQueue::new(2);
+        queue.push(2.0, EventKind::Wall(0, 1));
+        queue.push(1.0, EventKind::Balls(0, 1));
+        queue.push(3.0, EventKind::Wall(1, 2));
+        assert_eq!(queue.pop_until(1.5).map(|event| event.kind), Some(EventKind::Balls(0, 1)));
+        assert!(queue.pop_until(1.5).is_none());
+        assert_eq!(pop_all(&mut queue), [EventKind::Wall(0, 1), EventKind::Wall(1, 2)]);
+    }
+
+    #[test]
+    fn skips_events_of_invalidated_balls() {
+        let mut queue = EventQueue::new(3);
+        queue.push(1.0, EventKind::Balls(0, 1));
+        queue.push(2.0, EventKind::Wall(1, 0));
+        queue.push(3.0, EventKind::Balls(2, 1));
+        queue.push(4.0, EventKind::Wall(2, 3));
+        queue.invalidate(1);
+        queue.push(5.0, EventKind::Wall(1, 2));
+        assert_eq!(pop_all(&mut queue), [EventKind::Wall(2, 3), EventKind::Wall(1, 2)]);
+    }
+
+    #[test]
+    fn skips_events_of_removed_and_moved_balls() {
+        let mut queue = EventQueue::new(3);
+        queue.push(1.0, EventKind::Wall(0, 0));
+        queue.push(2.0, EventKind::Wall(1, 0));
+        queue.push(3.0, EventKind::Wall(2, 0));
+        queue.push(4.0, EventKind::Balls(1, 2));
+        // the last ball takes the place of the removed one, its events were predicted with its old index
+        queue.remove(0);
+        queue.push(5.0, EventKind::Wall(0, 1));
+        assert_eq!(pop_all(&mut queue), [EventKind::Wall(1, 0), EventKind::Wall(0, 1)]);
+
+        // removing the last ball leaves no ball in its place, so its old events can't become valid again
+        queue.push(6.0, EventKind::Wall(1, 2));
+        queue.remove(1);
+        queue.insert();
+        queue.push(7.0, EventKind::Wall(1, 3));
+        assert_eq!(pop_all(&mut queue), [EventKind::Wall(1, 3)]);
+    }
+
+    #[test]
+    fn drops_outdated_events_when_they_pile_up() {
+        let mut queue = EventQueue::new(1);
+        for i in 0..100_000 {
+            queue.invalidate(0);
+            queue.push(i as f64, EventKind::Wall(0, 0));
+            assert!(queue.heap.len() <= 16 * 65 + 1);
+        }
+        assert_eq!(pop_all(&mut queue), [EventKind::Wall(0, 0)]);
+    }
+}
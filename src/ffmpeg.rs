@@ -0,0 +1,10 @@
+use std::env;
+use std::path::PathBuf;
+
+
+/// Looks for an `ffmpeg` executable in the directories listed in the `PATH` environment variable.
+pub fn find_ffmpeg() -> Option<PathBuf> {
+    let name = if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" };
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).map(|dir| dir.join(name)).find(|path| path.is_file())
+}
@@ -0,0 +1,224 @@
+use std::ops::Range;
+
+use crate::ball::Ball;
+use crate::simulation::Interactions;
+
+
+// the most balls in a leaf of the tree, the forces between them and a ball are always summed up one by one
+const LEAF_SIZE: usize = 8;
+// the deepest a leaf can be, balls can't get closer than their radii, but those can be tiny
+const MAX_DEPTH: usize = 32;
+
+/// Returns the accelerations of all balls caused by the forces between them (gravity and electric charges).
+pub(crate) fn accelerations(balls: &[Ball], interactions: &Interactions) -> Vec<[f64; 2]> {
+    let tree = Tree::new(balls);
+    let mut stack = vec![];
+    (0..balls.len()).map(|ball| tree.acceleration(balls, ball, interactions, &mut stack)).collect()
+}
+
+// a quadtree of balls (Barnes-Hut), where each node knows the total mass and charge of the balls inside it and where they are
+struct Tree {
+    nodes: Vec<Node>,
+    // the balls ordered so that those inside each node are next to each other
+    order: Vec<usize>,
+}
+
+struct Node {
+    // the square that it covers
+    center: [f64; 2],
+    half_size: f64,
+    mass: f64,
+    mass_center: [f64; 2],
+    charge: f64,
+    // the center of the charges weighted by their magnitudes (charges of both signs don't have a proper center)
+    charge_center: [f64; 2],
+    // the range of `order` with the balls inside it
+    balls: Range<usize>,
+    // the index of the first of its four children, 0 for leaves (the root isn't anyone's child)
+    children: usize,
+}
+
+impl Tree {
+    fn new(balls: &[Ball]) -> Self {
+        let mut tree = Self { nodes: vec![], order: (0..balls.len()).collect() };
+        if balls.is_empty() {
+            return tree;
+        }
+        let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        for ball in balls {
+            min = [min[0].min(ball.x), min[1].min(ball.y)];
+            max = [max[0].max(ball.x), max[1].max(ball.y)];
+        }
+        let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+        let half_size = (max[0] - min[0]).max(max[1] - min[1]) / 2.0;
+        tree.nodes.push(tree.node(balls, center, half_size, 0..balls.len()));
+        tree.split(balls, 0, 0);
+        tree
+    }
+
+    // a leaf with the balls in `range` of `order`
+    fn node(&self, balls: &[Ball], center: [f64; 2], half_size: f64, range: Range<usize>) -> Node {
+        let (mut mass, mut mass_center, mut charge, mut charge_weight, mut charge_center) = (0.0, [0.0; 2], 0.0, 0.0, [0.0; 2]);
+        for &ball in &self.order[range.clone()] {
+            let ball = &balls[ball];
+            mass += ball.m;
+            mass_center = [mass_center[0] + ball.m * ball.x, mass_center[1] + ball.m * ball.y];
+            charge += ball.charge;
+            charge_weight += ball.charge.abs();
+            charge_center = [charge_center[0] + ball.charge.abs() * ball.x, charge_center[1] + ball.charge.abs() * ball.y];
+        }
+        Node {
+            center,
+            half_size,
+            mass,
+            mass_center: if mass > 0.0 { mass_center.map(|sum| sum / mass) } else { center },
+            charge,
+            charge_center: if charge_weight > 0.0 { charge_center.map(|sum| sum / charge_weight) } else { center },
+            balls: range,
+            children: 0,
+        }
+    }
+
+    // splits the node into four quarters, and those further, until there are only a few balls in each leaf
+    fn split(&mut self, balls: &[Ball], index: usize, depth: usize) {
+        let Node { center, half_size, balls: ref range, .. } = self.nodes[index];
+        let range = range.clone();
+        if range.len() <= LEAF_SIZE || depth == MAX_DEPTH {
+            return;
+        }
+        let quarter = |ball: usize| (balls[ball].x >= center[0]) as usize + 2 * (balls[ball].y >= center[1]) as usize;
+        self.order[range.clone()].sort_unstable_by_key(|&ball| quarter(ball));
+
+        let children = self.nodes.len();
+        self.nodes[index].children = children;
+        let mut start = range.start;
+        for child in 0..4 {
+            let end = start + self.order[start..range.end].iter().take_while(|&&ball| quarter(ball) == child).count();
+            let offset = half_size / 2.0;
+            let child_center = [center[0] + if child % 2 == 1 { offset } else { -offset }, center[1] + if child >= 2 { offset } else { -offset }];
+            let node = self.node(balls, child_center, offset, start..end);
+            self.nodes.push(node);
+            start = end;
+        }
+        for child in children..children + 4 {
+            self.split(balls, child, depth + 1);
+        }
+    }
+
+    // the acceleration of a ball, `stack` is reused between balls
+    fn acceleration(&self, balls: &[Ball], ball: usize, interactions: &Interactions, stack: &mut Vec<usize>) -> [f64; 2] {
+        let this = &balls[ball];
+        let mut acceleration = [0.0; 2];
+        let mut add = |pull: [f64; 2]| acceleration = [acceleration[0] + pull[0], acceleration[1] + pull[1]];
+        stack.clear();
+        stack.extend((!self.nodes.is_empty()).then_some(0));
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.balls.is_empty() {
+                continue;
+            }
+            if node.children == 0 {
+                for &other in &self.order[node.balls.clone()] {
+                    if other != ball {
+                        let other = &balls[other];
+                        add(pull(this, other.m, [other.x, other.y], other.charge, [other.x, other.y], interactions));
+                    }
+                }
+                continue;
+            }
+            // a node far enough away acts as a whole, but never the one the ball is in
+            let distance = (this.x - node.center[0]).hypot(this.y - node.center[1]);
+            let inside = (this.x - node.center[0]).abs() <= node.half_size && (this.y - node.center[1]).abs() <= node.half_size;
+            if !inside && 2.0 * node.half_size < interactions.theta * distance {
+                add(pull(this, node.mass, node.mass_center, node.charge, node.charge_center, interactions));
+            } else {
+                stack.extend(node.children..node.children + 4);
+            }
+        }
+        acceleration
+    }
+}
+
+// the acceleration of `ball` caused by a `mass` and a `charge` at the given centers
+fn pull(ball: &Ball, mass: f64, mass_center: [f64; 2], charge: f64, charge_center: [f64; 2], interactions: &Interactions) -> [f64; 2] {
+    let mut acceleration = [0.0; 2];
+    // towards the mass, with the strength G·m/d²
+    let [dx, dy] = [mass_center[0] - ball.x, mass_center[1] - ball.y];
+    let squared_distance = dx * dx + dy * dy;
+    if interactions.gravitational_constant != 0.0 && squared_distance > 0.0 {
+        let strength = interactions.gravitational_constant * mass / (squared_distance * squared_distance.sqrt());
+        acceleration = [strength * dx, strength * dy];
+    }
+    // away from a charge of the same sign, with the strength k·q·Q/(m·d²)
+    let [dx, dy] = [charge_center[0] - ball.x, charge_center[1] - ball.y];
+    let squared_distance = dx * dx + dy * dy;
+    if interactions.coulomb_constant != 0.0 && ball.charge != 0.0 && charge != 0.0 && squared_distance > 0.0 {
+        let strength = -interactions.coulomb_constant * ball.charge * charge / (ball.m * squared_distance * squared_distance.sqrt());
+        acceleration = [acceleration[0] + strength * dx, acceleration[1] + strength * dy];
+    }
+    acceleration
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn interactions(theta: f64) -> Interactions {
+        Interactions { gravitational_constant: 100.0, coulomb_constant: 1000.0, theta, ..Interactions::default() }
+    }
+
+    // the accelerations summed up over all pairs of balls
+    fn direct(balls: &[Ball], interactions: &Interactions) -> Vec<[f64; 2]> {
+        balls.iter().enumerate().map(|(i, ball)| {
+            balls.iter().enumerate().filter(|&(j, _)| j != i).fold([0.0; 2], |sum, (_, other)| {
+                let pull = pull(ball, other.m, [other.x, other.y], other.charge, [other.x, other.y], interactions);
+                [sum[0] + pull[0], sum[1] + pull[1]]
+            })
+        }).collect()
+    }
+
+    #[test]
+    fn approximates_direct_summation() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let balls: Vec<Ball> = (0..300).map(|_| Ball {
+            charge: rng.gen_range(0.0..5.0),
+            ..Ball::new(rng.gen_range(0.0..1000.0), rng.gen_range(0.0..800.0), rng.gen_range(1.0..10.0), 5.0, 0.0, 0.0, [0; 3])
+        }).collect();
+        let expected = direct(&balls, &interactions(0.0));
+        let errors = |theta| accelerations(&balls, &interactions(theta)).iter().zip(&expected)
+            .map(|(acceleration, expected)| (acceleration[0] - expected[0]).hypot(acceleration[1] - expected[1]))
+            .collect::<Vec<_>>();
+        for (theta, tolerance) in [(0.0, 1e-9), (0.2, 2e-3)] {
+            for (error, expected) in errors(theta).iter().zip(&expected) {
+                assert!(*error <= tolerance * expected[0].hypot(expected[1]), "{error} too big for {expected:?} with theta {theta}");
+            }
+        }
+        // on average about a percent
+        let magnitude: f64 = expected.iter().map(|expected| expected[0].hypot(expected[1])).sum();
+        assert!(errors(0.5).iter().sum::<f64>() <= 0.01 * magnitude);
+    }
+
+    #[test]
+    fn stops_splitting_balls_at_the_same_place() {
+        // more balls than fit in a leaf at the same place, and some a tiny bit apart, with one far away
+        let mut balls: Vec<Ball> = (0..20).map(|_| Ball { charge: 1.0, ..Ball::new(100.0, 100.0, 1.0, 5.0, 0.0, 0.0, [0; 3]) }).collect();
+        balls.extend((0..20).map(|i| Ball { charge: -1.0, ..Ball::new(300.0 + i as f64 * 1e-12, 300.0, 1.0, 5.0, 0.0, 0.0, [0; 3]) }));
+        balls.push(Ball::new(500.0, 100.0, 1.0, 5.0, 0.0, 0.0, [0; 3]));
+        let tree = Tree::new(&balls);
+        assert!(tree.nodes.len() <= 1 + 4 * 2 * MAX_DEPTH);
+        for theta in [0.0, 0.5] {
+            let accelerations = accelerations(&balls, &interactions(theta));
+            assert!(accelerations.iter().flatten().all(|a| a.is_finite()), "{accelerations:?}");
+            // the balls at the same place don't pull each other, only the others do
+            let expected = direct(&balls, &interactions(0.0));
+            let error = (accelerations[0][0] - expected[0][0]).hypot(accelerations[0][1] - expected[0][1]);
+            assert!(error <= 0.05 * expected[0][0].hypot(expected[0][1]));
+        }
+        // a single ball and none
+        assert_eq!(accelerations(&balls[..1], &interactions(0.5)), [[0.0, 0.0]]);
+        assert!(accelerations(&[], &interactions(0.5)).is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::ball::Ball;
+use crate::obstacle::Capsule;
+
+
+/// A uniform grid that splits the box into cells at least as big as the biggest ball.
+/// Two balls can only touch if they are in the same or neighbouring cells,
+/// so collisions only have to be predicted between balls in neighbouring cells.
+/// The cells on the edges extend to infinity, so balls outside the box are still in some cell.
+/// The parts of obstacles are kept in every cell they could overlap, so they are found the same way.
+/// In a periodic grid the cells on opposite edges are neighbours instead.
+pub(crate) struct Grid {
+    width: f64,
+    height: f64,
+    columns: usize,
+    rows: usize,
+    cell_width: f64,
+    cell_height: f64,
+    periodic: bool,
+    cells: Vec<Vec<usize>>,
+    ball_cells: Vec<(usize, usize)>,
+    capsule_cells: Vec<Vec<usize>>,
+    // the number of balls the cells were sized for
+    planned_balls: usize,
+}
+
+impl Grid {
+    pub fn new(width: f64, height: f64, balls: &[Ball], capsules: &[Capsule], periodic: bool) -> Self {
+        let max_diameter = balls.iter().map(|ball| 2.0 * ball.r).fold(0.0, f64::max);
+        // cells shouldn't be smaller than the biggest ball, and there is no point in having many more cells than balls
+        let min_cell_size = max_diameter.max((width * height / (4 * balls.len() + 1) as f64).sqrt());
+        let columns = ((width / min_cell_size).floor() as usize).max(1);
+        let rows = ((height / min_cell_size).floor() as usize).max(1);
+
+        let mut grid = Self {
+            width,
+            height,
+            columns,
+            rows,
+            cell_width: width / columns as f64,
+            cell_height: height / rows as f64,
+            periodic,
+            cells: vec![vec![]; columns * rows],
+            ball_cells: Vec::with_capacity(balls.len()),
+            capsule_cells: vec![vec![]; columns * rows],
+            planned_balls: balls.len(),
+        };
+        for (i, ball) in balls.iter().enumerate() {
+            let cell = grid.cell_of(ball.x, ball.y);
+            grid.cells[cell.1 * columns + cell.0].push(i);
+            grid.ball_cells.push(cell);
+        }
+        for (i, capsule) in capsules.iter().enumerate() {
+            let [x_min, y_min, x_max, y_max] = capsule.bounds();
+            let (first_column, first_row) = grid.cell_of(x_min, y_min);
+            let (last_column, last_row) = grid.cell_of(x_max, y_max);
+            for row in first_row..=last_row {
+                for column in first_column..=last_column {
+                    grid.capsule_cells[row * columns + column].push(i);
+                }
+            }
+        }
+        grid
+    }
+
+    fn cell_of(&self, x: f64, y: f64) -> (usize, usize) {
+        let column = ((x / self.cell_width).floor().max(0.0) as usize).min(self.columns - 1);
+        let row = ((y / self.cell_height).floor().max(0.0) as usize).min(self.rows - 1);
+        (column, row)
+    }
+
+    /// Returns the boundaries of the ball's cell that it can cross, in order left, right, bottom, top.
+    pub fn boundaries(&self, ball: usize) -> [Option<f64>; 4] {
+        let (column, row) = self.ball_cells[ball];
+        [
+            (column > 0 || self.periodic).then_some(column as f64 * self.cell_width),
+            (column + 1 < self.columns || self.periodic).then_some((column + 1) as f64 * self.cell_width),
+            (row > 0 || self.periodic).then_some(row as f64 * self.cell_height),
+            (row + 1 < self.rows || self.periodic).then_some((row + 1) as f64 * self.cell_height),
+        ]
+    }
+
+    /// Moves the ball into the neighbouring cell across the given boundary (0: left, 1: right, 2: bottom, 3: top).
+    /// Returns whether it crossed an edge of a periodic grid, and so came into the cell on the opposite edge.
+    pub fn cross(&mut self, ball: usize, boundary: usize) -> bool {
+        let (column, row) = self.ball_cells[ball];
+        let (columns, rows) = (self.columns, self.rows);
+        let (new_cell, wrapped) = match boundary {
+            0 => (((column + columns - 1) % columns, row), column == 0),
+            1 => (((column + 1) % columns, row), column + 1 == columns),
+            2 => ((column, (row + rows - 1) % rows), row == 0),
+            3 => ((column, (row + 1) % rows), row + 1 == rows),
+            _ => panic!("Invalid boundary"),
+        };
+
+        self.move_to(ball, new_cell);
+        wrapped
+    }
+
+    /// Moves the ball into the cell that contains the point (x, y), after it was moved there in some other way than by crossing boundaries.
+    pub fn update(&mut self, ball: usize, x: f64, y: f64) {
+        let cell = self.cell_of(x, y);
+        if cell != self.ball_cells[ball] {
+            self.move_to(ball, cell);
+        }
+    }
+
+    fn move_to(&mut self, ball: usize, new_cell: (usize, usize)) {
+        self.remove_from_cell(ball);
+        self.cells[new_cell.1 * self.columns + new_cell.0].push(ball);
+        self.ball_cells[ball] = new_cell;
+    }
+
+    fn remove_from_cell(&mut self, ball: usize) {
+        let (column, row) = self.ball_cells[ball];
+        let cell = &mut self.cells[row * self.columns + column];
+        if let Some(position) = cell.iter().position(|&other| other == ball) {
+            cell.swap_remove(position);
+        }
+    }
+
+    /// Adds a ball at (x, y) after the last one.
+    pub fn insert(&mut self, x: f64, y: f64) {
+        let cell = self.cell_of(x, y);
+        self.cells[cell.1 * self.columns + cell.0].push(self.ball_cells.len());
+        self.ball_cells.push(cell);
+    }
+
+    /// Removes the ball, the last ball takes its place.
+    pub fn remove(&mut self, ball: usize) {
+        self.remove_from_cell(ball);
+        let last = self.ball_cells.len() - 1;
+        if ball != last {
+            let (column, row) = self.ball_cells[last];
+            for other in &mut self.cells[row * self.columns + column] {
+                if *other == last {
+                    *other = ball;
+                }
+            }
+        }
+        self.ball_cells.swap_remove(ball);
+    }
+
+    /// Whether there are so many more balls than the cells were sized for that a new grid would be faster.
+    pub fn is_crowded(&self) -> bool {
+        self.ball_cells.len() > 2 * self.planned_balls
+    }
+
+    /// Whether a ball with the given radius fits into a cell, so that it can only touch balls in neighbouring cells.
+    pub fn fits(&self, radius: f64) -> bool {
+        2.0 * radius <= self.cell_width.min(self.cell_height)
+    }
+
+    /// Writes all balls in the ball's cell and the cells around it (except the ball itself) to `neighbours`.
+    pub fn neighbours(&self, ball: usize, neighbours: &mut Vec<usize>) {
+        let (column, row) = self.ball_cells[ball];
+        neighbours.clear();
+        for other_row in self.around(row, self.rows) {
+            for other_column in self.around(column, self.columns) {
+                neighbours.extend(self.cells[other_row * self.columns + other_column].iter().filter(|&&other| other != ball));
+            }
+        }
+    }
+
+    /// Returns the shifts of the other ball's position that bring the copies of it that the ball can touch next to it in a periodic grid
+    /// (one for neighbours and none for other balls, in a grid that is at most three cells wide or high all are neighbours,
+    /// and all copies on either side are used, because which of them is next to the ball changes when it moves to another cell),
+    /// in a grid that isn't periodic there is only no shift.
+    pub fn shifts(&self, ball: usize, other: usize) -> impl Iterator<Item = [f64; 2]> {
+        let ((column, row), (other_column, other_row)) = (self.ball_cells[ball], self.ball_cells[other]);
+        let shifts_x = self.axis_shifts(column, other_column, self.columns, self.width);
+        let shifts_y = self.axis_shifts(row, other_row, self.rows, self.height);
+        shifts_x.flat_map(move |shift_x| shifts_y.clone().map(move |shift_y| [shift_x, shift_y]))
+    }
+
+    // the shifts by the size of the grid along one axis that bring the column (or row) `other` next to `index`
+    fn axis_shifts(&self, index: usize, other: usize, count: usize, size: f64) -> impl Iterator<Item = f64> + Clone {
+        let periodic = self.periodic;
+        let difference = other as isize - index as isize;
+        (-1..=1)
+            .filter(move |&times: &isize| match periodic {
+                true => count <= 3 || (difference + times * count as isize).abs() <= 1,
+                false => times == 0,
+            })
+            .map(move |times| times as f64 * size)
+    }
+
+    /// Writes all balls that could touch a circle of the given radius around (x, y) to `nearby`
+    /// (all balls in cells that are at most `radius` and the radius of the biggest ball away).
+    pub fn nearby(&self, x: f64, y: f64, radius: f64, nearby: &mut Vec<usize>) {
+        let reach = radius + self.cell_width.min(self.cell_height) / 2.0;
+        let (first_column, last_column) = self.span(x - reach, x + reach, self.cell_width, self.columns);
+        let (first_row, last_row) = self.span(y - reach, y + reach, self.cell_height, self.rows);
+        nearby.clear();
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                nearby.extend(&self.cells[row.rem_euclid(self.rows as isize) as usize * self.columns + column.rem_euclid(self.columns as isize) as usize]);
+            }
+        }
+    }
+
+    // the first and last column (or row) of the cells between two coordinates,
+    // in a periodic grid they can be outside of it, and stand for the ones on the other side (each only once)
+    fn span(&self, start: f64, end: f64, cell_size: f64, count: usize) -> (isize, isize) {
+        let (first, last) = ((start / cell_size).floor() as isize, (end / cell_size).floor() as isize);
+        let last_index = count as isize - 1;
+        if self.periodic {
+            (first, last.min(first + last_index))
+        } else {
+            (first.clamp(0, last_index), last.clamp(0, last_index))
+        }
+    }
+
+    // the column (or row) `index` out of `count` and the ones next to it, each only once
+    // (in a periodic grid the first and the last one are next to each other)
+    fn around(&self, index: usize, count: usize) -> impl Iterator<Item = usize> {
+        let periodic = self.periodic;
+        (-1..=1).filter_map(move |offset: isize| {
+            let other = index as isize + offset;
+            if periodic {
+                // with fewer than three, the ones on both sides are the same
+                (offset == 0 || count > 2 || (offset == 1 && count == 2)).then(|| other.rem_euclid(count as isize) as usize)
+            } else {
+                (0..count as isize).contains(&other).then_some(other as usize)
+            }
+        })
+    }
+
+    /// Writes all parts of obstacles in the ball's cell and the cells around it to `capsules`.
+    pub fn capsules(&self, ball: usize, capsules: &mut Vec<usize>) {
+        let (column, row) = self.ball_cells[ball];
+        capsules.clear();
+        for other_row in self.around(row, self.rows) {
+            for other_column in self.around(column, self.columns) {
+                capsules.extend(&self.capsule_cells[other_row * self.columns + other_column]);
+            }
+        }
+        // big ones are in many cells
+        capsules.sort_unstable();
+        capsules.dedup();
+    }
+
+    /// Writes the balls that became neighbours of the ball when it crossed the given boundary to `neighbours`.
+    pub fn new_neighbours(&self, ball: usize, boundary: usize, neighbours: &mut Vec<usize>) {
+        self.new_cells(ball, boundary, &self.cells, neighbours);
+    }
+
+    /// Writes the parts of obstacles in the cells that became neighbours of the ball's cell when it crossed the given boundary to `capsules`
+    /// (some of them may have been in the old neighbouring cells too).
+    pub fn new_capsules(&self, ball: usize, boundary: usize, capsules: &mut Vec<usize>) {
+        self.new_cells(ball, boundary, &self.capsule_cells, capsules);
+        capsules.sort_unstable();
+        capsules.dedup();
+    }
+
+    // writes the contents of the cells that became neighbours of the ball's cell when it crossed the given boundary
+    fn new_cells(&self, ball: usize, boundary: usize, cells: &[Vec<usize>], contents: &mut Vec<usize>) {
+        let (column, row) = self.ball_cells[ball];
+        contents.clear();
+        // the column or row of cells that is now next to the ball's cell
+        match boundary {
+            0 | 1 => {
+                let Some(new_column) = self.next(column, if boundary == 0 { -1 } else { 1 }, self.columns) else {
+                    return;
+                };
+                for other_row in self.around(row, self.rows) {
+                    contents.extend(&cells[other_row * self.columns + new_column]);
+                }
+            },
+            2 | 3 => {
+                let Some(new_row) = self.next(row, if boundary == 2 { -1 } else { 1 }, self.rows) else {
+                    return;
+                };
+                for other_column in self.around(column, self.columns) {
+                    contents.extend(&cells[new_row * self.columns + other_column]);
+                }
+            },
+            _ => panic!("Invalid boundary"),
+        }
+    }
+
+    // the column (or row) next to `index` in the `direction` (-1 or 1) a ball just crossed into it in, which is new to its neighbourhood
+    // (None if there isn't any, in a periodic grid with at most three all of them already were neighbours)
+    fn next(&self, index: usize, direction: isize, count: usize) -> Option<usize> {
+        let next = index as isize + direction;
+        if self.periodic {
+            (count > 3).then(|| next.rem_euclid(count as isize) as usize)
+        } else {
+            (0..count as isize).contains(&next).then_some(next as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 5×3 grid of 20×20 cells, with balls in the corners and the middle
+    fn grid(periodic: bool) -> Grid {
+        let balls: Vec<Ball> = [(5.0, 5.0), (95.0, 5.0), (5.0, 55.0), (50.0, 30.0), (95.0, 55.0)].iter().map(|&(x, y)| Ball::new(x, y, 1.0, 5.0, 0.0, 0.0, [0; 3])).collect();
+        let grid = Grid::new(100.0, 60.0, &balls, &[], periodic);
+        assert_eq!((grid.columns, grid.rows), (5, 3));
+        grid
+    }
+
+    #[test]
+    fn finds_neighbours_in_the_cells_around() {
+        let grid = grid(false);
+        let mut neighbours = vec![];
+        grid.neighbours(0, &mut neighbours);
+        assert!(neighbours.is_empty());
+        grid.neighbours(3, &mut neighbours);
+        assert!(neighbours.is_empty());
+
+        let balls = [Ball::new(30.0, 30.0, 1.0, 5.0, 0.0, 0.0, [0; 3]), Ball::new(50.0, 30.0, 1.0, 5.0, 0.0, 0.0, [0; 3]), Ball::new(75.0, 30.0, 1.0, 5.0, 0.0, 0.0, [0; 3])];
+        let grid = Grid::new(100.0, 60.0, &balls, &[], false);
+        grid.neighbours(1, &mut neighbours);
+        neighbours.sort_unstable();
+        assert_eq!(neighbours, [0, 2]);
+        grid.neighbours(0, &mut neighbours);
+        assert_eq!(neighbours, [1]);
+    }
+
+    #[test]
+    fn moves_balls_across_boundaries() {
+        let mut grid = grid(false);
+        assert_eq!(grid.boundaries(0), [None, Some(20.0), None, Some(20.0)]);
+        assert_eq!(grid.boundaries(3), [Some(40.0), Some(60.0), Some(20.0), Some(40.0)]);
+        assert!(!grid.cross(0, 1));
+        assert_eq!(grid.ball_cells[0], (1, 0));
+        let mut neighbours = vec![];
+        grid.new_neighbours(0, 1, &mut neighbours);
+        assert_eq!(neighbours, [3]);
+        assert!(!grid.cross(0, 3));
+        assert_eq!(grid.ball_cells[0], (1, 1));
+        assert_eq!(grid.cells[1], Vec::<usize>::new());
+        assert_eq!(grid.cells[6], [0]);
+    }
+
+    #[test]
+    fn joins_the_edges_of_a_periodic_grid() {
+        let (open, periodic) = (grid(false), grid(true));
+        assert_eq!(open.around(0, 5).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(open.around(4, 5).collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(periodic.around(0, 5).collect::<Vec<_>>(), [4, 0, 1]);
+        assert_eq!(periodic.around(4, 5).collect::<Vec<_>>(), [3, 4, 0]);
+        assert_eq!(periodic.around(1, 2).collect::<Vec<_>>(), [1, 0]);
+        assert_eq!(periodic.around(0, 1).collect::<Vec<_>>(), [0]);
+
+        let mut neighbours = vec![];
+        open.neighbours(0, &mut neighbours);
+        assert!(neighbours.is_empty());
+        periodic.neighbours(0, &mut neighbours);
+        neighbours.sort_unstable();
+        assert_eq!(neighbours, [1, 2, 4]);
+    }
+
+    #[test]
+    fn shifts_copies_next_to_the_ball() {
+        let (open, periodic) = (grid(false), grid(true));
+        assert_eq!(open.shifts(0, 4).collect::<Vec<_>>(), [[0.0, 0.0]]);
+        // with three rows all of them are neighbours, so the copies on both sides are used
+        assert_eq!(periodic.shifts(0, 4).collect::<Vec<_>>(), [[-100.0, -60.0], [-100.0, 0.0], [-100.0, 60.0]]);
+        assert_eq!(periodic.shifts(1, 0).collect::<Vec<_>>(), [[100.0, -60.0], [100.0, 0.0], [100.0, 60.0]]);
+        // two columns apart isn't next to it either way
+        assert_eq!(periodic.shifts(3, 0).count(), 0);
+        let mut grid = periodic;
+        grid.update(3, 30.0, 30.0);
+        assert_eq!(grid.shifts(3, 4).count(), 0);
+        assert_eq!(grid.shifts(3, 0).collect::<Vec<_>>(), [[0.0, -60.0], [0.0, 0.0], [0.0, 60.0]]);
+    }
+
+    #[test]
+    fn wraps_balls_crossing_the_edge() {
+        let mut periodic = grid(true);
+        periodic.update(3, 70.0, 30.0);
+        assert_eq!(periodic.boundaries(0), [Some(0.0), Some(20.0), Some(0.0), Some(20.0)]);
+        assert!(periodic.cross(0, 0));
+        assert_eq!(periodic.ball_cells[0], (4, 0));
+        let mut neighbours = vec![];
+        periodic.new_neighbours(0, 0, &mut neighbours);
+        assert_eq!(neighbours, [3]);
+        assert!(!periodic.cross(0, 0));
+        assert!(periodic.cross(0, 2));
+        assert_eq!(periodic.ball_cells[0], (3, 2));
+    }
+}
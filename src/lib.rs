@@ -0,0 +1,67 @@
+//! A simulation of bouncing circles with continuous collision detection.
+//!
+//! ```no_run
+//! use circle_bounce_rs::{BallSettings, ImageRGB8, RenderMode, Simulation};
+//!
+//! let settings = BallSettings::default();
+//! let balls = settings.generate(1920.0, 1080.0, &mut rand::thread_rng()).unwrap();
+//! let mut simulation = Simulation::new(1920.0, 1080.0, balls);
+//! let mut image = ImageRGB8::new(1920, 1080, settings.background_color);
+//! for _ in 0..60 {
+//!     simulation.step(1.0 / 60.0);
+//!     simulation.render(RenderMode::Smooth, &mut image);
+//! }
+//! ```
+
+mod arena;
+mod ball;
+mod error;
+mod events;
+mod ffmpeg;
+mod forces;
+mod grid;
+mod motion;
+mod obstacle;
+mod output;
+mod pipeline;
+mod render;
+mod scene;
+mod setup;
+mod simulation;
+
+pub use arena::Arena;
+pub use ball::{Ball, MassModel};
+pub use error::Error;
+pub use ffmpeg::find_ffmpeg;
+pub use obstacle::{Obstacle, Rotation, Shape};
+pub use output::{Codec, EncoderSettings, FfmpegSink, FrameSink, ImageSequenceSink, OutputFormat, RawSink};
+pub use pipeline::{RenderSettings, render_simulation};
+pub use render::{RenderMode, generate_frame};
+pub use scene::Scene;
+pub use setup::BallSettings;
+pub use simulation::{Fragmentation, Interactions, MovingWalls, Physics, Simulation, calculate_collision, calculate_obstacle_collision, calculate_wall_collision};
+pub use tinydraw::ImageRGB8;
+
+
+/// Parses a color in `#rrggbb` (or `rrggbb`) format.
+#[allow(clippy::needless_range_loop)]
+pub fn hex_to_rgb(hex: &str) -> Result<[u8; 3], &'static str> {
+    let mut rgb = [0; 3];
+    let mut hex = hex.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err("Invalid hex color");
+    }
+    for i in 0..3 {
+        match u8::from_str_radix(&hex[..2], 16) {
+            Ok(v) => rgb[i] = v,
+            Err(_) => return Err("Invalid hex color"),
+        }
+        hex = &hex[2..];
+    }
+    Ok(rgb)
+}
+
+/// Formats a color in `#rrggbb` format.
+pub fn rgb_to_hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
@@ -1,43 +1,36 @@
 #![allow(non_snake_case)]
 
-use std::f64::consts::PI;
 use std::fs::File;
+use std::io::{self, BufWriter};
+#[cfg(feature = "bundled-ffmpeg")]
 use std::io::Write;
+#[cfg(feature = "bundled-ffmpeg")]
+use std::path::Path;
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::process;
 use std::time::Instant;
 
 use clap::{Arg, ArgAction, ArgMatches, command, value_parser};
 use indicatif::ProgressBar;
-use rand::{Rng, thread_rng};
-use rand::rngs::ThreadRng;
-use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "bundled-ffmpeg")]
 use tempfile::tempdir;
-use tinydraw::ImageRGB8;
 
+use circle_bounce_rs::{Arena, BallSettings, Codec, EncoderSettings, Error, FfmpegSink, Fragmentation, FrameSink, ImageSequenceSink, Interactions, MassModel, MovingWalls, OutputFormat, Physics, RawSink, RenderMode, RenderSettings, Scene, Simulation, hex_to_rgb, render_simulation};
+#[cfg(not(feature = "bundled-ffmpeg"))]
+use circle_bounce_rs::find_ffmpeg;
 
-#[allow(clippy::needless_range_loop)]
-fn hex_to_rgb(hex: &str) -> Result<[u8; 3], &'static str> {
-    let mut rgb = [0; 3];
-    let mut hex = hex.trim_start_matches('#');
-    for i in 0..3 {
-        match u8::from_str_radix(&hex[..2], 16) {
-            Ok(v) => rgb[i] = v,
-            Err(_) => return Err("Invalid hex color"),
-        }
-        hex = &hex[2..];
-    }
-    Ok(rgb)
-}
 
-fn main() {
-    let bundled_ffmpeg = include_bytes!("../data/ffmpeg-compressed.exe");
+#[cfg(all(feature = "bundled-ffmpeg", not(windows)))]
+compile_error!("The bundled-ffmpeg feature is only available on Windows");
 
+fn main() {
     let arguments: ArgMatches = command!()
         .next_line_help(true)
         .arg(Arg::new("destination_file")
             .value_name("FILE")
-            .help("The file to save the video to")
+            .help("The file to save the video to (a directory for image sequences, - for raw frames to stdout)")
             .required(true)
             .value_parser(value_parser!(PathBuf)))
         .arg(Arg::new("video_length")
@@ -142,385 +135,537 @@ fn main() {
             .required(false)
             .value_parser(["circle", "ball"])
             .default_value("circle"))
+        .arg(Arg::new("seed")
+            .long("seed")
+            .value_name("SEED")
+            .help("The seed for the random generator (random if not set)")
+            .required(false)
+            .value_parser(value_parser!(u64)))
+        .arg(Arg::new("scene")
+            .long("scene")
+            .value_name("FILE")
+            .help("Load the initial state from a scene file (TOML or JSON), instead of generating random balls")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(Arg::new("dump_scene")
+            .long("dump-scene")
+            .value_name("FILE")
+            .help("Save the initial state to a scene file (TOML or JSON)")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(Arg::new("ffmpeg")
+            .long("ffmpeg")
+            .value_name("PATH")
+            .help("The ffmpeg executable to use (searched for in PATH if not set)")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(Arg::new("output_format")
+            .long("output-format")
+            .value_name("FORMAT")
+            .help("The format of the output (detected from FILE if not set)")
+            .required(false)
+            .value_parser(["video", "png", "ppm", "raw"]))
+        .arg(Arg::new("codec")
+            .long("codec")
+            .value_name("CODEC")
+            .help("The codec of the video")
+            .required(false)
+            .value_parser(["x264", "x265", "vp9", "av1", "prores"])
+            .default_value("x264"))
+        .arg(Arg::new("crf")
+            .long("crf")
+            .value_name("CRF")
+            .help("The constant rate factor (quality) of the video, lower is better (x264 is lossless if neither CRF nor bitrate are set)")
+            .required(false)
+            .value_parser(value_parser!(u32)))
+        .arg(Arg::new("bitrate")
+            .long("bitrate")
+            .value_name("BITRATE")
+            .help("The target bitrate of the video (e.g. 5M)")
+            .required(false))
+        .arg(Arg::new("preset")
+            .long("preset")
+            .value_name("PRESET")
+            .help("The encoder preset (e.g. slow, veryfast)")
+            .required(false))
+        .arg(Arg::new("pixel_format")
+            .long("pixel-format")
+            .value_name("FORMAT")
+            .help("The pixel format of the video (e.g. yuv420p for playback in browsers)")
+            .required(false))
+        .arg(Arg::new("ffmpeg_args")
+            .long("ffmpeg-args")
+            .value_name("ARGS")
+            .help("Additional arguments for ffmpeg, separated by spaces (e.g. \"-tune animation\")")
+            .required(false)
+            .allow_hyphen_values(true))
+        .arg(Arg::new("threads")
+            .long("threads")
+            .value_name("NUM")
+            .help("The number of threads drawing frames (number of CPU cores if not set)")
+            .required(false)
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("render_mode")
+            .long("render-mode")
+            .value_name("MODE")
+            .help("The way the balls are drawn (aliased rounds them to whole pixels)")
+            .required(false)
+            .value_parser(["aliased", "smooth"])
+            .default_value("aliased"))
+        .arg(Arg::new("motion_blur")
+            .long("motion-blur")
+            .value_name("SAMPLES")
+            .help("Blur moving balls by averaging this many moments during each frame")
+            .required(false)
+            .value_parser(value_parser!(usize))
+            .default_value("1"))
+        .arg(Arg::new("outline_width")
+            .long("outline-width")
+            .value_name("WIDTH")
+            .help("The width of the outlines of the balls (drawn inside their edge)")
+            .required(false)
+            .value_parser(value_parser!(f64))
+            .default_value("0"))
+        .arg(Arg::new("outline_color")
+            .long("outline-color")
+            .value_name("COLOR")
+            .help("The color of the outlines of the balls (HEX) (the same as the color of each ball if not set)")
+            .required(false)
+            .value_parser(hex_to_rgb))
+        .arg(Arg::new("no_fill")
+            .long("no-fill")
+            .action(ArgAction::SetTrue)
+            .help("Draw only the outlines of the balls")
+            .required(false))
+        .arg(Arg::new("opacity")
+            .long("opacity")
+            .value_name("OPACITY")
+            .help("The opacity of the balls, from 0 (invisible) to 1 (solid)")
+            .required(false)
+            .value_parser(value_parser!(f64))
+            .default_value("1"))
+        .arg(Arg::new("spoke_color")
+            .long("spoke-color")
+            .value_name("COLOR")
+            .help("Draw a spoke of this color (HEX) in every ball to show its rotation")
+            .required(false)
+            .value_parser(hex_to_rgb))
+        .arg(Arg::new("charge")
+            .long("charge")
+            .value_name("CHARGE")
+            .help("Give every ball this electric charge, with a random sign (only matters with --coulomb) [default: 0]")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("gravity")
+            .long("gravity")
+            .value_name("X,Y")
+            .help("The acceleration of all balls (e.g. 0,-500 pulls them down) (overrides the one in the scene file)")
+            .required(false)
+            .allow_hyphen_values(true)
+            .value_parser(parse_vector))
+        .arg(Arg::new("restitution")
+            .long("restitution")
+            .value_name("E")
+            .help("The coefficient of restitution of collisions between balls, from 0 (they stick together) to 1 (elastic) (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("wall_restitution")
+            .long("wall-restitution")
+            .value_name("E")
+            .help("The coefficient of restitution of collisions with walls and obstacles (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("drag")
+            .long("drag")
+            .value_name("K")
+            .help("The linear drag, the deceleration of balls per unit of velocity (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("quadratic_drag")
+            .long("quadratic-drag")
+            .value_name("K")
+            .help("The quadratic drag, the deceleration of balls per unit of velocity squared (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("friction")
+            .long("friction")
+            .value_name("MU")
+            .help("The coefficient of friction between balls, which makes them spin (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("wall_friction")
+            .long("wall-friction")
+            .value_name("MU")
+            .help("The coefficient of friction between balls and walls or obstacles (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("merge")
+            .long("merge")
+            .action(ArgAction::SetTrue)
+            .help("Merge colliding balls into one instead of bouncing, keeping their mass and momentum (the size of merged balls follows --ball_mass)")
+            .required(false))
+        .arg(Arg::new("fragment_speed")
+            .long("fragment-speed")
+            .value_name("SPEED")
+            .help("Break balls that collide faster than this into fragments, keeping their mass and momentum (the size of fragments follows --ball_mass) (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("min_fragment_radius")
+            .long("min-fragment-radius")
+            .value_name("RADIUS")
+            .help("The radius of the smallest fragments, balls whose fragments would be smaller don't break [default: 5]")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("fragments")
+            .long("fragments")
+            .value_name("NUM")
+            .help("The number of fragments a ball breaks into [default: 4]")
+            .required(false)
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("arena")
+            .long("arena")
+            .value_name("SHAPE")
+            .help("The shape of the box the balls bounce around in: the whole frame, the biggest circle in its center, the ellipse that fills it or the whole frame without walls, where balls leaving it on one side come back on the other [default: rectangle] (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(["rectangle", "circle", "ellipse", "periodic"]))
+        .arg(Arg::new("wall_speeds")
+            .long("wall-speeds")
+            .value_name("LEFT,RIGHT,BOTTOM,TOP")
+            .help("Move the walls of a rectangular arena into it with these speeds, like pistons, negative ones move them out (e.g. 0,0,0,100 pushes the top one down) (overrides the ones in the scene file)")
+            .required(false)
+            .value_parser(parse_walls))
+        .arg(Arg::new("wall_offsets")
+            .long("wall-offsets")
+            .value_name("LEFT,RIGHT,BOTTOM,TOP")
+            .help("Start the walls of a rectangular arena this far inside it, generated balls are placed between them (e.g. 0,400,0,0 with --wall-speeds 0,-100,0,0 lets the balls spread out to the right) (overrides the ones in the scene file)")
+            .required(false)
+            .value_parser(parse_walls))
+        .arg(Arg::new("wall_duration")
+            .long("wall-duration")
+            .value_name("SECONDS")
+            .help("How long the walls move before they stop [default: the length of the video]")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("pairwise_gravity")
+            .long("pairwise-gravity")
+            .value_name("G")
+            .help("Make balls attract each other by Newtonian gravity with this gravitational constant (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("coulomb")
+            .long("coulomb")
+            .value_name("K")
+            .help("Make charged balls attract or repel each other with this Coulomb constant (overrides the one in the scene file)")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("time_step")
+            .long("time-step")
+            .value_name("SECONDS")
+            .help("The longest time between two updates of the velocities by the forces between balls [default: 0.002]")
+            .required(false)
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("barnes_hut")
+            .long("barnes-hut")
+            .value_name("THETA")
+            .help("How far groups of balls have to be to act as one on other balls, relative to their size (0 calculates the forces between all pairs exactly) [default: 0.5]")
+            .required(false)
+            .value_parser(value_parser!(f64)))
         .get_matches();
 
-    let dir = tempdir().unwrap();
-    let ffmpeg_path = dir.path().join("ffmpeg.exe");
-    let mut file = File::create(&ffmpeg_path).unwrap();
-    file.write_all(bundled_ffmpeg).unwrap();
-    drop(file);
+    if let Err(err) = setup_simulation(arguments) {
+        eprintln!("Error: {}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+#[cfg(feature = "bundled-ffmpeg")]
+fn extract_bundled_ffmpeg(dir: &Path) -> Result<PathBuf, Error> {
+    let bundled_ffmpeg = include_bytes!("../data/ffmpeg-compressed.exe");
+    let ffmpeg_path = dir.join("ffmpeg.exe");
+    let mut file = File::create(&ffmpeg_path).map_err(|err| Error::io("Can't extract bundled ffmpeg", err))?;
+    file.write_all(bundled_ffmpeg).map_err(|err| Error::io("Can't extract bundled ffmpeg", err))?;
+    Ok(ffmpeg_path)
+}
 
-    setup_simulation(arguments, ffmpeg_path.to_str().unwrap());
-    drop(dir);
+// parses a vector in "x,y" format
+fn parse_vector(vector: &str) -> Result<[f64; 2], &'static str> {
+    let (x, y) = vector.split_once(',').ok_or("Expected two numbers separated by a comma")?;
+    let x: f64 = x.trim().parse().map_err(|_| "Invalid number")?;
+    let y: f64 = y.trim().parse().map_err(|_| "Invalid number")?;
+    if !x.is_finite() || !y.is_finite() {
+        return Err("Numbers must be finite");
+    }
+    Ok([x, y])
 }
 
-fn setup_simulation(cli_arguments: ArgMatches, ffmpeg_path: &str) {
-    let destination_file = cli_arguments.get_one::<PathBuf>("destination_file").unwrap().to_str().unwrap();
+// parses the speeds of the four walls in "left,right,bottom,top" format
+fn parse_walls(values: &str) -> Result<[f64; 4], &'static str> {
+    let values: Vec<f64> = values.split(',').map(|value| value.trim().parse().map_err(|_| "Invalid number")).collect::<Result<_, _>>()?;
+    let values: [f64; 4] = values.try_into().map_err(|_| "Expected four numbers separated by commas")?;
+    if !values.iter().all(|value| value.is_finite()) {
+        return Err("Numbers must be finite");
+    }
+    Ok(values)
+}
+
+fn setup_simulation(cli_arguments: ArgMatches) -> Result<(), Error> {
     let video_length = *cli_arguments.get_one::<u128>("video_length").unwrap();
     if video_length == 0 {
-        println!("Video length must be greater than 0");
-        return;
+        return Err(Error::Validation(String::from("Video length must be greater than 0")));
     }
     let fps = *cli_arguments.get_one::<u128>("fps").unwrap();
     if fps == 0 {
-        println!("FPS must be greater than 0");
-        return;
+        return Err(Error::Validation(String::from("FPS must be greater than 0")));
     }
-    let width = *cli_arguments.get_one::<u128>("width").unwrap();
-    if width == 0 {
-        println!("Width must be greater than 0");
-        return;
+    let mut render_settings = RenderSettings {
+        num_of_frames: (video_length * fps) as usize,
+        fps,
+        mode: match cli_arguments.get_one::<String>("render_mode").unwrap().as_str() {
+            "aliased" => RenderMode::Aliased,
+            "smooth" => RenderMode::Smooth,
+            _ => unreachable!("Invalid render mode"),
+        },
+        ..RenderSettings::default()
+    };
+    if let Some(&threads) = cli_arguments.get_one::<usize>("threads") {
+        if threads == 0 {
+            return Err(Error::Validation(String::from("Number of threads must be greater than 0")));
+        }
+        render_settings.threads = threads;
     }
-    let height = *cli_arguments.get_one::<u128>("height").unwrap();
-    if height == 0 {
-        println!("Height must be greater than 0");
-        return;
+    render_settings.motion_blur = *cli_arguments.get_one::<usize>("motion_blur").unwrap();
+    if render_settings.motion_blur == 0 {
+        return Err(Error::Validation(String::from("Number of motion blur samples must be greater than 0")));
     }
-    let num_of_balls = *cli_arguments.get_one::<u128>("num_of_balls").unwrap();
-    if num_of_balls == 0 {
-        println!("Number of balls must be greater than 0");
-        return;
+    let mut scene = match cli_arguments.get_one::<PathBuf>("scene") {
+        Some(scene_file) => {
+            let mut scene = Scene::load(scene_file)?;
+            if let Some(arena) = arena(&cli_arguments) {
+                // the balls have to fit into the new one
+                scene.physics.arena = arena;
+                scene.validate()?;
+            }
+            scene
+        },
+        None => generate_scene(&cli_arguments)?,
+    };
+    if let Some(&gravity) = cli_arguments.get_one::<[f64; 2]>("gravity") {
+        scene.physics.gravity = gravity;
     }
-    let background_color: [u8; 3] = *cli_arguments.get_one::<[u8; 3]>("background_color").unwrap();
-    let ball_color: [u8; 3] = *cli_arguments.get_one::<[u8; 3]>("ball_color").unwrap();
-    let ball_color_random: bool = cli_arguments.get_flag("ball_color_random");
-    let ball_radius_min: f64 = *cli_arguments.get_one::<u128>("ball_radius_min").unwrap() as f64;
-    let ball_radius_max: f64 = *cli_arguments.get_one::<u128>("ball_radius_max").unwrap() as f64;
-    if ball_radius_max < ball_radius_min {
-        println!("Maximum radius must be greater than or equal to minimum radius");
-        return;
-    } else if ball_radius_max == 0.0 {
-        println!("Maximum radius must be greater than 0");
-        return;
+    if let Some(&restitution) = cli_arguments.get_one::<f64>("restitution") {
+        scene.physics.restitution = restitution;
     }
-    let ball_speed_min: f64 = *cli_arguments.get_one::<u128>("ball_speed_min").unwrap() as f64;
-    let ball_speed_max: f64 = *cli_arguments.get_one::<u128>("ball_speed_max").unwrap() as f64;
-    if ball_speed_max < ball_speed_min {
-        println!("Maximum speed must be greater than or equal to minimum speed");
-        return;
-    } else if ball_speed_max == 0.0 {
-        println!("Maximum speed must be greater than 0");
-        return;
+    if let Some(&restitution) = cli_arguments.get_one::<f64>("wall_restitution") {
+        scene.physics.wall_restitution = restitution;
     }
-    let ball_mass = cli_arguments.get_one::<String>("ball_mass").unwrap().as_str();
-
-    let mut balls: Vec<Ball> = vec![];
-    let mut rng: ThreadRng = thread_rng();
-    for _ in 0..num_of_balls {
-        // radius
-        let radius = rng.gen_range((ball_radius_min as u128)..=(ball_radius_max as u128)) as f64;
-
-        // x, y
-        let mut tries = 0;
-        let mut x: f64;
-        let mut y: f64;
-        'outer: loop {
-            tries += 1;
-            if tries > 100_000 {
-                println!("Can't fit all balls in the given area");
-                return;
-            }
-            x = rng.gen_range(radius..(width as f64 - radius - 1.0));
-            y = rng.gen_range(radius..(height as f64 - radius - 1.0));
-
-            for ball in &balls {
-                if ((x - ball.x).abs() <= (radius + ball.r)) && ((y - ball.y).abs() <= (radius + ball.r)) {
-                    continue 'outer;
-                }
-            }
-            break;
+    if let Some(&drag) = cli_arguments.get_one::<f64>("drag") {
+        scene.physics.linear_drag = drag;
+    }
+    if let Some(&drag) = cli_arguments.get_one::<f64>("quadratic_drag") {
+        scene.physics.quadratic_drag = drag;
+    }
+    if let Some(&friction) = cli_arguments.get_one::<f64>("friction") {
+        scene.physics.friction = friction;
+    }
+    if let Some(&friction) = cli_arguments.get_one::<f64>("wall_friction") {
+        scene.physics.wall_friction = friction;
+    }
+    if cli_arguments.get_flag("merge") {
+        scene.physics.merging = Some(mass_model(&cli_arguments));
+    }
+    if let Some(&speed) = cli_arguments.get_one::<f64>("fragment_speed") {
+        let fragmentation = scene.physics.fragmentation.unwrap_or_default();
+        scene.physics.fragmentation = Some(Fragmentation { speed, model: mass_model(&cli_arguments), ..fragmentation });
+    }
+    if let Some(fragmentation) = &mut scene.physics.fragmentation {
+        if let Some(&min_radius) = cli_arguments.get_one::<f64>("min_fragment_radius") {
+            fragmentation.min_radius = min_radius;
+        }
+        if let Some(&fragments) = cli_arguments.get_one::<usize>("fragments") {
+            fragmentation.fragments = fragments;
         }
-
-        // mass
-        let mass = match ball_mass {
-            "circle" => radius * radius * PI,
-            "ball" => (radius * radius * radius * PI * 4.0) / 3.0,
-            _ => panic!("Invalid ball mass type"),
-        };
-
-        // speed (vx, vy)
-        let speed_x: f64 = (*[-1, 1].choose(&mut rng).unwrap() as f64) * rng.gen_range(ball_speed_min..=ball_speed_max);
-        let speed_y: f64 = (*[-1, 1].choose(&mut rng).unwrap() as f64) * rng.gen_range(ball_speed_min..=ball_speed_max);
-
-        // color
-        let color: [u8; 3] = if ball_color_random {
-            let mut color_temp: [u8; 3] = [rng.gen_range(0..=255), rng.gen_range(0..=255), rng.gen_range(0..=255)];
-            while color_temp == background_color {
-                color_temp = [rng.gen_range(0..=255), rng.gen_range(0..=255), rng.gen_range(0..=255)];
-            }
-            color_temp
-        } else {
-            ball_color
-        };
-
-        balls.push(Ball::new(x, y, mass, radius, speed_x, speed_y, color));
     }
-
-    drop(rng);
-    run_simulation(ffmpeg_path, destination_file, video_length, fps, width, height, background_color, balls);
-}
-
-#[allow(clippy::too_many_arguments)]
-fn run_simulation(ffmpeg_path: &str, destination_file: &str, video_length: u128, fps: u128, width: u128, height: u128, background_color: [u8; 3], mut balls: Vec<Ball>) {
-    let start_time = Instant::now();
-    let pb = ProgressBar::new((video_length * fps) as u64);
-
-    let mut ffmpeg_encoder = Command::new(ffmpeg_path)
-        .arg("-y") // overwrite file if it already exists
-        .arg("-f").arg("rawvideo") // interpret the information from stdin as "raw video"
-        .arg("-pix_fmt").arg("rgb24") // every three bytes are [r, g, b] pixel
-        .arg("-s").arg(format!("{}x{}", width, height)) // the size of the video
-        .arg("-r").arg(fps.to_string()) // the fps of the video
-        .arg("-an") // don't use audio
-        .arg("-i").arg("-") // get data from stdin
-        .arg("-c:v").arg("libx264") // encode to h264
-        .arg("-crf").arg("0") // variable video bitrate
-        .arg(destination_file) // output file
-        .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()) // set stdin to be piped
-        .spawn().unwrap(); // Run the child command
-    let stdin = ffmpeg_encoder.stdin.as_mut().unwrap();
-
-    let width = width as f64;
-    let height = height as f64;
-    let interval = 1.0 / (fps as f64);
-
-    let mut times: Vec<Vec<Option<f64>>> = vec![];
-    for ball1 in 0..(balls.len() - 1) {
-        let mut times_ball1: Vec<Option<f64>> = vec![];
-        for _ in (ball1 + 1)..balls.len() {
-            times_ball1.push(None);
+    if cli_arguments.contains_id("wall_speeds") || cli_arguments.contains_id("wall_offsets") {
+        scene.physics.moving_walls.get_or_insert(MovingWalls { duration: video_length as f64, ..MovingWalls::default() });
+    }
+    if let Some(moving_walls) = &mut scene.physics.moving_walls {
+        if let Some(&speeds) = cli_arguments.get_one::<[f64; 4]>("wall_speeds") {
+            moving_walls.speeds = speeds;
+        }
+        if let Some(&offsets) = cli_arguments.get_one::<[f64; 4]>("wall_offsets") {
+            moving_walls.offsets = offsets;
+        }
+        if let Some(&duration) = cli_arguments.get_one::<f64>("wall_duration") {
+            moving_walls.duration = duration;
         }
-        times.push(times_ball1);
     }
-    let mut wall_times: Vec<[Option<f64>; 4]> = vec![[None; 4]; balls.len()];
-    let mut image: ImageRGB8 = ImageRGB8::new(width as usize, height as usize, background_color);
-    for _ in 0..(fps * video_length) {
-        for ball1 in 0..(balls.len() - 1) {
-            for ball2 in (ball1 + 1)..balls.len() {
-                times[ball1][ball2 - ball1 - 1] = calculate_collision(&mut balls, ball1, ball2);
-            }
+    if cli_arguments.contains_id("pairwise_gravity") || cli_arguments.contains_id("coulomb") {
+        scene.physics.interactions.get_or_insert_with(Interactions::default);
+    }
+    if let Some(interactions) = &mut scene.physics.interactions {
+        if let Some(&gravitational_constant) = cli_arguments.get_one::<f64>("pairwise_gravity") {
+            interactions.gravitational_constant = gravitational_constant;
         }
-        for ball in 0..balls.len() {
-            for wall in 0..4 {
-                wall_times[ball][wall] = calculate_wall_collision(&mut balls, ball, wall, width, height);
-            }
+        if let Some(&coulomb_constant) = cli_arguments.get_one::<f64>("coulomb") {
+            interactions.coulomb_constant = coulomb_constant;
         }
+        if let Some(&time_step) = cli_arguments.get_one::<f64>("time_step") {
+            interactions.time_step = time_step;
+        }
+        if let Some(&theta) = cli_arguments.get_one::<f64>("barnes_hut") {
+            interactions.theta = theta;
+        }
+    }
+    if cli_arguments.contains_id("wall_speeds") || cli_arguments.contains_id("wall_offsets") || cli_arguments.contains_id("wall_duration") {
+        // the balls have to be between the walls at the start and fit between them where they stop
+        scene.validate()?;
+    } else {
+        scene.physics.validate()?;
+    }
 
-        let mut moved_time: f64 = 0.0;
-        while moved_time < interval {
-            let mut ball_2_ball_col: u8 = 0;  // 0: no collision | 1: ball 2 ball collision | 2: ball 2 wall collision
-            let time_left: f64 = interval - moved_time;
-            let mut smallest_time: f64 = time_left;
-            let mut smallest_ind: [usize; 2] = [0, 0];
-
-            for x in 0..times.len() {
-                for y in 0..times[x].len() {
-                    if times[x][y].is_some() && times[x][y].unwrap() <= smallest_time {
-                        smallest_ind[0] = x;
-                        smallest_ind[1] = x + y + 1;
-                        smallest_time = times[x][y].unwrap();
-                        ball_2_ball_col = 1;
-                    }
-                }
-            }
-
-            for x in 0..wall_times.len() {
-                for y in 0..wall_times[x].len() {
-                    if wall_times[x][y].is_some() && wall_times[x][y].unwrap() <= smallest_time {
-                        smallest_ind[0] = x;
-                        smallest_ind[1] = y;
-                        smallest_time = wall_times[x][y].unwrap();
-                        ball_2_ball_col = 2;
-                    }
-                }
-            }
-
-            match ball_2_ball_col {
-                0 => {
-                    move_balls(&mut balls, time_left);
-                    moved_time += time_left;
-                },
-                1 => {
-                    move_balls(&mut balls, smallest_time);
-
-                    let d = balls[smallest_ind[0]].r + balls[smallest_ind[1]].r;
-                    let nx = (balls[smallest_ind[1]].x - balls[smallest_ind[0]].x) / d;
-                    let ny = (balls[smallest_ind[1]].y - balls[smallest_ind[0]].y) / d;
-                    let p = (2.0 * (nx * (balls[smallest_ind[0]].v_x - balls[smallest_ind[1]].v_x) + ny * (balls[smallest_ind[0]].v_y - balls[smallest_ind[1]].v_y))) / (balls[smallest_ind[0]].m + balls[smallest_ind[1]].m);
-                    balls[smallest_ind[0]].v_x -= p * balls[smallest_ind[1]].m * nx;
-                    balls[smallest_ind[0]].v_y -= p * balls[smallest_ind[1]].m * ny;
-                    balls[smallest_ind[1]].v_x += p * balls[smallest_ind[0]].m * nx;
-                    balls[smallest_ind[1]].v_y += p * balls[smallest_ind[0]].m * ny;
-
-                    for x in 0..times.len() {
-                        for y in 0..times[x].len() {
-                            if smallest_ind.contains(&x) || smallest_ind.contains(&(x + y + 1)) {
-                                if x == smallest_ind[0] && (x + y + 1) == smallest_ind[1] {
-                                    times[x][y] = None;
-                                } else {
-                                    times[x][y] = calculate_collision(&mut balls, x, x + y + 1);
-                                }
-                            } else if times[x][y].is_some() {
-                                times[x][y] = Some(times[x][y].unwrap() - smallest_time);
-                            }
-                        }
-                    }
-
-                    for x in 0..wall_times.len() {
-                        for y in 0..4 {
-                            if smallest_ind.contains(&x) {
-                                wall_times[x][y] = calculate_wall_collision(&mut balls, x, y, width, height);
-                            } else if wall_times[x][y].is_some() {
-                                wall_times[x][y] = Some(wall_times[x][y].unwrap() - smallest_time);
-                            }
-                        }
-                    }
+    if let Some(dump_file) = cli_arguments.get_one::<PathBuf>("dump_scene") {
+        scene.save(dump_file)?;
+    }
 
-                    moved_time += smallest_time;
-                },
-                2 => {
-                    move_balls(&mut balls, smallest_time);
-                    if smallest_ind[1] < 2 {
-                        balls[smallest_ind[0]].v_x *= -1.0;
-                    } else {
-                        balls[smallest_ind[0]].v_y *= -1.0;
-                    }
+    // the bundled ffmpeg is extracted to a temporary directory, which is deleted at the end of this function
+    #[cfg(feature = "bundled-ffmpeg")]
+    let bundled_dir = tempdir().map_err(|err| Error::io("Can't create temporary directory", err))?;
+
+    let ffmpeg_path = match cli_arguments.get_one::<PathBuf>("ffmpeg") {
+        Some(ffmpeg_path) => Some(ffmpeg_path.clone()),
+        None => {
+            #[cfg(feature = "bundled-ffmpeg")]
+            let ffmpeg_path = Some(extract_bundled_ffmpeg(bundled_dir.path())?);
+            #[cfg(not(feature = "bundled-ffmpeg"))]
+            let ffmpeg_path = find_ffmpeg();
+            ffmpeg_path
+        }
+    };
 
-                    for x in 0..times.len() {
-                        for y in 0..times[x].len() {
-                            if x == smallest_ind[0] || (x + y + 1) == smallest_ind[0] {
-                                times[x][y] = calculate_collision(&mut balls, x, x + y + 1);
-                            } else if times[x][y].is_some() {
-                                times[x][y] = Some(times[x][y].unwrap() - smallest_time);
-                            }
-                        }
-                    }
+    render_settings.background_color = scene.background_color;
+    let output = create_output(&cli_arguments, ffmpeg_path, scene.width as usize, scene.height as usize, fps)?;
+    run_simulation(output, &render_settings, scene.simulation())
+}
 
-                    for x in 0..wall_times.len() {
-                        for y in 0..4 {
-                            if x == smallest_ind[0] {
-                                if y == smallest_ind[1] {
-                                    wall_times[x][y] = None;
-                                } else {
-                                    wall_times[x][y] = calculate_wall_collision(&mut balls, x, y, width, height);
-                                }
-                            } else if wall_times[x][y].is_some() {
-                                wall_times[x][y] = Some(wall_times[x][y].unwrap() - smallest_time);
-                            }
-                        }
-                    }
+fn generate_scene(cli_arguments: &ArgMatches) -> Result<Scene, Error> {
+    let width = *cli_arguments.get_one::<u128>("width").unwrap() as f64;
+    let height = *cli_arguments.get_one::<u128>("height").unwrap() as f64;
+    let settings = BallSettings {
+        num_of_balls: *cli_arguments.get_one::<u128>("num_of_balls").unwrap() as usize,
+        radius_min: *cli_arguments.get_one::<u128>("ball_radius_min").unwrap() as f64,
+        radius_max: *cli_arguments.get_one::<u128>("ball_radius_max").unwrap() as f64,
+        speed_min: *cli_arguments.get_one::<u128>("ball_speed_min").unwrap() as f64,
+        speed_max: *cli_arguments.get_one::<u128>("ball_speed_max").unwrap() as f64,
+        mass: mass_model(cli_arguments),
+        color: *cli_arguments.get_one::<[u8; 3]>("ball_color").unwrap(),
+        color_random: cli_arguments.get_flag("ball_color_random"),
+        background_color: *cli_arguments.get_one::<[u8; 3]>("background_color").unwrap(),
+        outline_color: cli_arguments.get_one::<[u8; 3]>("outline_color").copied(),
+        outline_width: *cli_arguments.get_one::<f64>("outline_width").unwrap(),
+        filled: !cli_arguments.get_flag("no_fill"),
+        opacity: *cli_arguments.get_one::<f64>("opacity").unwrap(),
+        spoke_color: cli_arguments.get_one::<[u8; 3]>("spoke_color").copied(),
+        charge: cli_arguments.get_one::<f64>("charge").copied().unwrap_or_default(),
+    };
 
-                    moved_time += smallest_time;
-                },
-                _ => panic!("Invalid collision type"),
-            }
+    let seed = match cli_arguments.get_one::<u64>("seed") {
+        Some(seed) => *seed,
+        None => {
+            let seed: u64 = thread_rng().gen();
+            eprintln!("Seed: {}", seed);
+            seed
         }
+    };
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
 
-        generate_frame(&balls, &mut image);
-        stdin.write_all(image.to_bytes()).unwrap();
-        pb.inc(1);
+    let arena = arena(cli_arguments).unwrap_or_default();
+    // the balls are placed between the walls where they start
+    let [left, right, bottom, top] = cli_arguments.get_one::<[f64; 4]>("wall_offsets").copied().unwrap_or_default();
+    if !(left + right < width && bottom + top < height) {
+        return Err(Error::Validation(String::from("The walls must start inside the box")));
     }
-
-    let _output = ffmpeg_encoder.wait_with_output().unwrap();
-    let elapsed_time = start_time.elapsed().as_millis();
-    pb.finish();
-    println!("Finished encoding in {}.{} s", elapsed_time / 1000, elapsed_time % 1000);
-}
-
-fn move_balls(balls: &mut Vec<Ball>, interval: f64) {
-    for ball in balls {
-        ball.x += ball.v_x * interval;
-        ball.y += ball.v_y * interval;
+    let mut balls = settings.generate_in(arena, width - left - right, height - bottom - top, &mut rng)?;
+    for ball in &mut balls {
+        ball.x += left;
+        ball.y += bottom;
     }
+    Ok(Scene { width, height, background_color: settings.background_color, balls, obstacles: vec![], physics: Physics { arena, ..Physics::default() } })
 }
 
-fn calculate_collision(balls: &mut [Ball], ball1: usize, ball2: usize) -> Option<f64> {
-    // write position of balls as functions of time (x + vx*t, y + vy*t)
-	// write distance of 2 balls with those functions
-	// square to get rid of square root
-	// find minimum value of that distance^2 function, and if it is smaller than d^2, find solutions for that function, take the one that happens sooner
-
-    let d_pow2 = (balls[ball1].r + balls[ball2].r).powi(2); // distance between balls at collision squared (d^2)
-    let delta_x = balls[ball1].x - balls[ball2].x; // x1 - x2
-	let delta_y = balls[ball1].y - balls[ball2].y; // y1 - y2
-	let delta_vx = balls[ball1].v_x - balls[ball2].v_x; // vx1 - vx2
-	let delta_vy = balls[ball1].v_y - balls[ball2].v_y; // vy1 - vy2
-
-    // calculate coefficients of distance^2 function
-	let a = delta_vx.powi(2) + delta_vy.powi(2); // first coefficient
-    if a != 0.0 { // if a is 0, then function is not quadratic, balls aren't moving, therefore, there is no collision
-        let b_divis_2 = (delta_x * delta_vx) + (delta_y * delta_vy);  // second coefficient divided by 2 (it simplifies function when in that form)
-        let c = delta_x.powi(2) + delta_y.powi(2);  // third coefficient
-
-        if (c - (b_divis_2.powi(2) / a)) < d_pow2 { // if minimum value of distance^2 function is smaller than d^2, then the balls would collide
-            // find solutions for function, when it's value is d^2
-            let discriminant_sqrt = (b_divis_2.powi(2) - (a * (c - d_pow2))).sqrt();
-            let mut sol_1: Option<f64> = Some((- b_divis_2 - discriminant_sqrt) / a);
-            let mut sol_2: Option<f64> = Some((- b_divis_2 + discriminant_sqrt) / a);
-            if sol_1.unwrap() < 0.0 {
-                sol_1 = None;
-            }
-            if sol_2.unwrap() < 0.0 {
-                sol_2 = None;
-            }
+fn arena(cli_arguments: &ArgMatches) -> Option<Arena> {
+    cli_arguments.get_one::<String>("arena").map(|arena| match arena.as_str() {
+        "rectangle" => Arena::Rectangle,
+        "circle" => Arena::Circle,
+        "ellipse" => Arena::Ellipse,
+        "periodic" => Arena::Periodic,
+        _ => unreachable!("Invalid arena"),
+    })
+}
 
-            if sol_1.is_some() {
-                return if sol_2.is_some() {
-                    Some(sol_1.unwrap().min(sol_2.unwrap()))
-                } else {
-                    sol_1
-                }
-            } else if sol_2.is_some() && sol_1.is_none() {
-                return sol_2;
-            }
-        }
+fn mass_model(cli_arguments: &ArgMatches) -> MassModel {
+    match cli_arguments.get_one::<String>("ball_mass").unwrap().as_str() {
+        "circle" => MassModel::Circle,
+        "ball" => MassModel::Ball,
+        _ => unreachable!("Invalid ball mass type"),
     }
-    None
 }
 
-fn calculate_wall_collision(balls: &mut [Ball], ball: usize, wall: usize, width: f64, height: f64) -> Option<f64> {
-    // end position minus start position divided by speed
-    let result: f64 = match wall {
-        0 => (balls[ball].r - balls[ball].x) / balls[ball].v_x, // left
-        1 => (width - balls[ball].r - 1.0 - balls[ball].x) / balls[ball].v_x, // right
-        2 => (balls[ball].r - balls[ball].y) / balls[ball].v_y, // bottom
-        3 => (height - balls[ball].r - 1.0 - balls[ball].y) / balls[ball].v_y, // top
-        _ => panic!("Invalid wall"),
+fn create_output(cli_arguments: &ArgMatches, ffmpeg_path: Option<PathBuf>, width: usize, height: usize, fps: u128) -> Result<Box<dyn FrameSink>, Error> {
+    let destination = cli_arguments.get_one::<PathBuf>("destination_file").unwrap();
+    let output_format = match cli_arguments.get_one::<String>("output_format").map(String::as_str) {
+        Some("video") => OutputFormat::Video,
+        Some("png") => OutputFormat::Png,
+        Some("ppm") => OutputFormat::Ppm,
+        Some("raw") => OutputFormat::Raw,
+        Some(_) => unreachable!("Invalid output format"),
+        None => OutputFormat::detect(destination),
     };
-    if result > 0.0 {
-        Some(result)
-    } else {
-        None
-    }
-}
 
-fn generate_frame(balls: &[Ball], img: &mut ImageRGB8) {
-    img.clear();
-    for ball in balls {
-        img.draw_circle((ball.x).round() as usize, (ball.y).round() as usize, (ball.r).round() as usize, ball.color, 0, 1.0);
-    }
+    Ok(match output_format {
+        OutputFormat::Video => {
+            let ffmpeg_path = ffmpeg_path.ok_or_else(|| Error::Encoder(String::from("ffmpeg not found, install it and add it to PATH or set its location with --ffmpeg")))?;
+            let encoder = EncoderSettings {
+                codec: match cli_arguments.get_one::<String>("codec").unwrap().as_str() {
+                    "x264" => Codec::X264,
+                    "x265" => Codec::X265,
+                    "vp9" => Codec::Vp9,
+                    "av1" => Codec::Av1,
+                    "prores" => Codec::ProRes,
+                    _ => unreachable!("Invalid codec"),
+                },
+                crf: cli_arguments.get_one::<u32>("crf").copied(),
+                bitrate: cli_arguments.get_one::<String>("bitrate").cloned(),
+                preset: cli_arguments.get_one::<String>("preset").cloned(),
+                pixel_format: cli_arguments.get_one::<String>("pixel_format").cloned(),
+                extra_args: match cli_arguments.get_one::<String>("ffmpeg_args") {
+                    Some(args) => args.split_whitespace().map(String::from).collect(),
+                    None => vec![],
+                },
+            };
+            Box::new(FfmpegSink::new(&ffmpeg_path, destination, width, height, fps, &encoder)?)
+        },
+        OutputFormat::Png | OutputFormat::Ppm => Box::new(ImageSequenceSink::new(destination, output_format)?),
+        OutputFormat::Raw => {
+            if destination.as_os_str() == "-" {
+                Box::new(RawSink::new(BufWriter::new(io::stdout())))
+            } else {
+                let file = File::create(destination).map_err(|err| Error::io(format!("Can't create {}", destination.display()), err))?;
+                Box::new(RawSink::new(BufWriter::new(file)))
+            }
+        },
+    })
 }
 
-struct Ball {
-    x: f64,
-    y: f64,
-    m: f64,
-    r: f64,
-    v_x: f64,
-    v_y: f64,
-    color: [u8; 3]
-}
+fn run_simulation(mut output: Box<dyn FrameSink>, render_settings: &RenderSettings, mut simulation: Simulation) -> Result<(), Error> {
+    let start_time = Instant::now();
+    let pb = ProgressBar::new(render_settings.num_of_frames as u64);
 
-impl Ball {
-    fn new(x: f64, y: f64, mass: f64, radius: f64, velocity_x: f64, velocity_y: f64, color: [u8; 3]) -> Self {
-        Self {
-            x,
-            y,
-            m: mass,
-            r: radius,
-            v_x: velocity_x,
-            v_y: velocity_y,
-            color
-        }
+    if let Err(err) = render_simulation(&mut simulation, render_settings, output.as_mut(), || pb.inc(1)) {
+        pb.abandon();
+        return Err(err);
     }
+    let elapsed_time = start_time.elapsed().as_millis();
+    pb.finish();
+    eprintln!("Finished encoding in {}.{} s", elapsed_time / 1000, elapsed_time % 1000);
+    Ok(())
 }
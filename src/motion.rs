@@ -0,0 +1,505 @@
+/// The motion of a ball along one axis under a constant acceleration and drag,
+/// `dv/dt = acceleration - linear_drag * v - quadratic_drag * |v| * v`.
+///
+/// The velocity always moves monotonically towards the terminal velocity, so the position is convex or concave in time,
+/// and two balls on the same axis never overtake each other's velocity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Axis {
+    pub acceleration: f64,
+    pub linear_drag: f64,
+    pub quadratic_drag: f64,
+}
+
+impl Axis {
+    /// Whether the motion is slowed by drag.
+    pub fn has_drag(&self) -> bool {
+        self.linear_drag != 0.0 || self.quadratic_drag != 0.0
+    }
+
+    // the same motion with the axis flipped
+    fn flipped(&self) -> Self {
+        Self { acceleration: -self.acceleration, ..*self }
+    }
+
+    /// The speed that is approached over time.
+    pub fn terminal_speed(&self) -> f64 {
+        let acceleration = self.acceleration.abs();
+        let k = (self.linear_drag * self.linear_drag + 4.0 * self.quadratic_drag * acceleration).sqrt();
+        if acceleration == 0.0 { 0.0 } else { self.acceleration.signum() * 2.0 * acceleration / (self.linear_drag + k) }
+    }
+
+    /// The acceleration (including drag) while moving with `speed`.
+    pub fn acceleration_at(&self, speed: f64) -> f64 {
+        self.acceleration - self.linear_drag * speed - self.quadratic_drag * speed.abs() * speed
+    }
+
+    /// How far a ball starting with `speed` gets in total (infinity unless the drag stops it without any acceleration).
+    pub fn max_distance(&self, speed: f64) -> f64 {
+        if self.acceleration == 0.0 && self.linear_drag > 0.0 {
+            // it slows down forever and only gets so far
+            let (k, speed) = (self.linear_drag, speed.abs());
+            if self.quadratic_drag == 0.0 { speed / k } else { (self.quadratic_drag * speed / k).ln_1p() / self.quadratic_drag }
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// Returns the distance travelled in `time` starting with `speed`, and the speed at the end.
+    pub fn advance(&self, speed: f64, time: f64) -> (f64, f64) {
+        if !self.has_drag() {
+            return ((speed + 0.5 * self.acceleration * time) * time, speed + self.acceleration * time);
+        }
+        // the drag depends on the direction of movement, so the motion is split where the speed changes sign
+        let direction = if speed != 0.0 { speed.signum() } else if self.acceleration != 0.0 { self.acceleration.signum() } else { return (0.0, 0.0) };
+        let acceleration = direction * self.acceleration;
+        let stop_time = self.stop_time(direction * speed, acceleration);
+        if time < stop_time {
+            let (distance, speed) = self.advance_forward(direction * speed, acceleration, time);
+            (direction * distance, direction * speed)
+        } else {
+            let (distance, _) = self.advance_forward(direction * speed, acceleration, stop_time);
+            let (rest, speed) = self.advance(0.0, time - stop_time);
+            (direction * distance + rest, speed)
+        }
+    }
+
+    // `advance` for a non-negative speed that stays non-negative
+    fn advance_forward(&self, speed: f64, acceleration: f64, time: f64) -> (f64, f64) {
+        let (a, c) = (self.linear_drag, self.quadratic_drag);
+        let discriminant = a * a + 4.0 * c * acceleration;
+        if discriminant >= 0.0 {
+            // dv/dt = -c(v - p)(v - q), written in a form that stays precise when c or p - q are small
+            let k = discriminant.sqrt();
+            let p = if a + k > 0.0 { 2.0 * acceleration / (a + k) } else { 0.0 }; // the terminal speed
+            let e = if k > 0.0 { -(-k * time).exp_m1() / k } else { time };
+            let w = speed - p;
+            if c == 0.0 {
+                (p * time + w * e, p + w * (-k * time).exp())
+            } else {
+                (p * time + (c * w * e).ln_1p() / c, p + w * (-k * time).exp() / (1.0 + c * w * e))
+            }
+        } else {
+            // decelerating with complex roots, the speed follows a tangent
+            let h = a / (2.0 * c);
+            let omega = (-discriminant).sqrt() / 2.0;
+            let width = omega / c;
+            let angle = ((speed + h) / width).atan();
+            let end_angle = angle - omega * time;
+            (-h * time + (end_angle.cos() / angle.cos()).ln() / c, width * end_angle.tan() - h)
+        }
+    }
+
+    // the time after which a non-negative speed reaches 0 (infinity if it never does)
+    fn stop_time(&self, speed: f64, acceleration: f64) -> f64 {
+        if acceleration >= 0.0 {
+            return f64::INFINITY;
+        }
+        let (a, c) = (self.linear_drag, self.quadratic_drag);
+        let discriminant = a * a + 4.0 * c * acceleration;
+        if discriminant >= 0.0 {
+            let k = discriminant.sqrt();
+            let p = 2.0 * acceleration / (a + k);
+            let z = speed / ((speed - p) * (k - p * c));
+            if k > 0.0 { -(-k * z).ln_1p() / k } else { z }
+        } else {
+            let h = a / (2.0 * c);
+            let omega = (-discriminant).sqrt() / 2.0;
+            let width = omega / c;
+            (((speed + h) / width).atan() - (h / width).atan()) / omega
+        }
+    }
+
+    /// Returns the time after which a coordinate moving with `speed` reaches the `upper` (or lower) limit `target`
+    /// while moving towards it (if it is already past it and moving towards it, that is right away).
+    pub fn time_to_reach(&self, position: f64, speed: f64, target: f64, upper: bool) -> Option<f64> {
+        if !self.has_drag() {
+            return time_to_reach(position, speed, self.acceleration, target, upper);
+        }
+        // a lower limit is an upper limit with the axis flipped
+        let (axis, speed, distance) = if upper { (*self, speed, target - position) } else { (self.flipped(), -speed, position - target) };
+
+        if distance <= 0.0 && speed > 0.0 {
+            return Some(0.0);
+        }
+        let start = if speed > 0.0 {
+            // moving towards it, it is reached unless the drag or acceleration stop it first
+            let stop_time = axis.stop_time(speed, axis.acceleration);
+            if stop_time.is_finite() {
+                if axis.advance(speed, stop_time).0 < distance {
+                    return None;
+                }
+                return Some(axis.solve(speed, distance, 0.0, 0.0, stop_time));
+            }
+            if axis.max_distance(speed) <= distance {
+                return None;
+            }
+            0.0
+        } else if axis.acceleration > 0.0 {
+            // moving away from it (or standing still), but the acceleration turns it around
+            let stop_time = axis.stop_time(-speed, -axis.acceleration);
+            if axis.advance(speed, stop_time).0 >= distance {
+                // it is past the limit and turns around before getting back, so it moves towards it from then on
+                return Some(stop_time);
+            }
+            stop_time
+        } else {
+            return None;
+        };
+
+        // the limit is reached eventually, find a time by which it surely is
+        let mut end = start + 1.0;
+        while axis.advance(speed, end).0 < distance {
+            end = start + 2.0 * (end - start);
+            if !end.is_finite() {
+                return None;
+            }
+        }
+        Some(axis.solve(speed, distance, 0.0, start, end))
+    }
+
+    /// Like [Axis::time_to_reach], for a limit that moves with `target_speed` (starting at `target`).
+    pub fn time_to_meet(&self, position: f64, speed: f64, target: f64, target_speed: f64, upper: bool) -> Option<f64> {
+        if target_speed == 0.0 {
+            return self.time_to_reach(position, speed, target, upper);
+        }
+        if !self.has_drag() {
+            // relative to the limit, the coordinate moves with the same acceleration
+            return time_to_reach(position, speed - target_speed, self.acceleration, target, upper);
+        }
+        let (axis, speed, target_speed, distance) = if upper { (*self, speed, target_speed, target - position) } else { (self.flipped(), -speed, -target_speed, position - target) };
+        // the distance that is left at `time`
+        let gap = |time: f64| distance + target_speed * time - axis.advance(speed, time).0;
+
+        if distance <= 0.0 && speed > target_speed {
+            return Some(0.0);
+        }
+        // the speed changes monotonically towards the terminal speed, so it passes the speed of the limit at most once
+        let terminal_speed = axis.terminal_speed();
+        let start = if speed > target_speed {
+            if terminal_speed < target_speed {
+                // it only catches up until it gets slower than the limit
+                let turn = axis.time_to_speed(speed, target_speed)?;
+                if gap(turn) > 0.0 {
+                    return None;
+                }
+                return Some(axis.solve(speed, distance, target_speed, 0.0, turn));
+            }
+            0.0
+        } else if terminal_speed > target_speed {
+            // it gets faster than the limit later
+            let turn = axis.time_to_speed(speed, target_speed)?;
+            if gap(turn) <= 0.0 {
+                // it is past the limit and gets faster than it before getting back, so it moves towards it from then on
+                return Some(turn);
+            }
+            turn
+        } else {
+            return None;
+        };
+
+        // the limit is reached eventually, unless the speeds only get equal over time, find a time by which it surely is
+        let mut end = start + 1.0;
+        while gap(end) > 0.0 {
+            end = start + 2.0 * (end - start);
+            if !end.is_finite() {
+                return None;
+            }
+        }
+        Some(axis.solve(speed, distance, target_speed, start, end))
+    }
+
+    // the time after which a coordinate moving with `speed` gets to move with `target_speed`
+    // (None if it never does, because the terminal speed isn't beyond it)
+    fn time_to_speed(&self, speed: f64, target_speed: f64) -> Option<f64> {
+        if speed == target_speed {
+            return Some(0.0);
+        }
+        if (target_speed - speed) * (self.terminal_speed() - target_speed) <= 0.0 {
+            return None;
+        }
+        // bisection, the speed changes monotonically
+        let faster = speed > target_speed;
+        let (mut start, mut end) = (0.0, 1.0);
+        while (self.advance(speed, end).1 > target_speed) == faster {
+            (start, end) = (end, 2.0 * end);
+            if !end.is_finite() {
+                return None;
+            }
+        }
+        while end - start > f64::EPSILON * end {
+            let middle = start + (end - start) / 2.0;
+            if (self.advance(speed, middle).1 > target_speed) == faster {
+                start = middle;
+            } else {
+                end = middle;
+            }
+        }
+        Some(end)
+    }
+
+    // finds the time in `start..=end` at which the distance travelled is `distance` farther than a limit moving with `target_speed`,
+    // it has to be gaining on it there and reach `distance` at the end, but not at the start
+    fn solve(&self, speed: f64, distance: f64, target_speed: f64, mut start: f64, mut end: f64) -> f64 {
+        // Newton's method, falling back to bisection whenever it would leave the bracket
+        let mut time = start;
+        for _ in 0..100 {
+            let (travelled, current_speed) = self.advance(speed, time);
+            let travelled = travelled - target_speed * time;
+            if travelled == distance {
+                return time;
+            } else if travelled < distance {
+                start = time;
+            } else {
+                end = time;
+            }
+            let newton = time + (distance - travelled) / (current_speed - target_speed);
+            let next = if newton > start && newton < end { newton } else { start + (end - start) / 2.0 };
+            if (next - time).abs() <= f64::EPSILON * next.abs() {
+                return next;
+            }
+            time = next;
+        }
+        time
+    }
+}
+
+// returns the time after which a coordinate moving with `speed` and `acceleration` reaches the `upper` (or lower) limit `target`
+// while moving towards it (if it is already past it and moving towards it, that is right away)
+fn time_to_reach(position: f64, speed: f64, acceleration: f64, target: f64, upper: bool) -> Option<f64> {
+    // a lower limit is an upper limit with the axis flipped
+    let (position, speed, acceleration, target) = if upper { (position, speed, acceleration, target) } else { (-position, -speed, -acceleration, -target) };
+
+    // solve position + speed*t + acceleration/2*t^2 = target
+    let distance = target - position;
+    let discriminant = speed * speed + 2.0 * acceleration * distance;
+    if distance <= 0.0 && speed > 0.0 {
+        Some(0.0)
+    } else if distance > 0.0 && speed > 0.0 {
+        // moving towards it, it is reached unless the acceleration turns it around first
+        // (the smaller solution written in a form that doesn't lose precision when the acceleration is small)
+        (discriminant >= 0.0).then(|| 2.0 * distance / (speed + discriminant.sqrt()))
+    } else if acceleration > 0.0 {
+        // moving away from it (or standing still), but the acceleration turns it around
+        if discriminant <= 0.0 {
+            // it is past the limit and turns around before getting back, so it moves towards it from then on
+            Some(-speed / acceleration)
+        } else {
+            Some((discriminant.sqrt() - speed) / acceleration)
+        }
+    } else {
+        None
+    }
+}
+
+/// Returns the first time at which a point moving along a parabola from `position` (relative to a fixed point),
+/// with `velocity` and `acceleration`, is `distance` away from the fixed point while moving towards it (if `approaching`) or away from it
+/// (if it is already past that distance and moving on, that is right away).
+pub(crate) fn time_to_distance(position: [f64; 2], velocity: [f64; 2], acceleration: [f64; 2], distance: f64, approaching: bool) -> Option<f64> {
+    let ([cx, cy], [vx, vy], [gx, gy]) = (position, velocity, acceleration);
+    // the squared distance minus distance^2 as a polynomial of time
+    let coefficients = [
+        cx * cx + cy * cy - distance * distance,
+        2.0 * (cx * vx + cy * vy),
+        vx * vx + vy * vy + cx * gx + cy * gy,
+        vx * gx + vy * gy,
+        (gx * gx + gy * gy) / 4.0,
+    ];
+    // moving towards the fixed point is the same as moving away from it with the polynomial flipped
+    let direction = if approaching { -1.0 } else { 1.0 };
+    if direction * coefficients[0] >= 0.0 && direction * coefficients[1] > 0.0 {
+        return Some(0.0);
+    }
+    let slope = |time: f64| coefficients.iter().enumerate().skip(1).rev().fold(0.0, |sum, (power, c)| sum * time + power as f64 * c);
+    polynomial_roots(&coefficients, 0.0).into_iter().find(|&time| direction * slope(time) > 0.0)
+}
+
+// the real roots of a polynomial (coefficients from the lowest power) that are at least `start`, in increasing order,
+// found in the intervals between the roots of its derivative, where it is monotonic (roots where it only touches 0 can be missed)
+fn polynomial_roots(coefficients: &[f64], start: f64) -> Vec<f64> {
+    let Some(degree) = coefficients.iter().rposition(|&c| c != 0.0).filter(|&degree| degree > 0) else {
+        return vec![];
+    };
+    let coefficients = &coefficients[..=degree];
+    let value = |x: f64| coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c);
+    // all roots are smaller than this (Cauchy's bound)
+    let end = start.max(0.0) + 1.0 + coefficients[..degree].iter().map(|c| (c / coefficients[degree]).abs()).fold(0.0, f64::max);
+
+    let derivative: Vec<f64> = coefficients.iter().enumerate().skip(1).map(|(power, c)| power as f64 * c).collect();
+    let slope = |x: f64| derivative.iter().rev().fold(0.0, |sum, c| sum * x + c);
+    let mut bounds = vec![start];
+    bounds.extend(polynomial_roots(&derivative, start).into_iter().filter(|&x| x < end));
+    bounds.push(end);
+
+    let mut roots = vec![];
+    if value(start) == 0.0 {
+        roots.push(start);
+    }
+    for pair in bounds.windows(2) {
+        let (mut low, mut high) = (pair[0], pair[1]);
+        let (value_low, value_high) = (value(low), value(high));
+        if value_high == 0.0 {
+            roots.push(high);
+        } else if value_low != 0.0 && (value_low < 0.0) != (value_high < 0.0) {
+            // Newton's method, which is safe because the polynomial is monotonic in the interval,
+            // with bisection whenever a step would leave the part of the interval that is left
+            let mut root = low + (high - low) / 2.0;
+            for _ in 0..100 {
+                let value_root = value(root);
+                if value_root == 0.0 {
+                    break;
+                }
+                if (value_root < 0.0) == (value_low < 0.0) {
+                    low = root;
+                } else {
+                    high = root;
+                }
+                let step = value_root / slope(root);
+                let next = if root - step > low && root - step < high { root - step } else { low + (high - low) / 2.0 };
+                // as precise as it can be, but not more than it can be for times around a nanosecond
+                // (otherwise it could go on through all the tiny numbers for roots close to 0)
+                let done = (next - root).abs() <= f64::EPSILON * next.abs().max(1e-9);
+                root = next;
+                if done || high - low <= f64::EPSILON * high.abs().max(1e-9) {
+                    break;
+                }
+            }
+            roots.push(root);
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AXES: [Axis; 7] = [
+        Axis { acceleration: -300.0, linear_drag: 0.0, quadratic_drag: 0.0 },
+        Axis { acceleration: 0.0, linear_drag: 0.8, quadratic_drag: 0.0 },
+        Axis { acceleration: 200.0, linear_drag: 1.5, quadratic_drag: 0.0 },
+        Axis { acceleration: 0.0, linear_drag: 0.0, quadratic_drag: 0.01 },
+        Axis { acceleration: -400.0, linear_drag: 0.0, quadratic_drag: 0.002 },
+        Axis { acceleration: 150.0, linear_drag: 0.5, quadratic_drag: 0.003 },
+        // decelerating with complex roots
+        Axis { acceleration: -50.0, linear_drag: 2.0, quadratic_drag: 0.1 },
+    ];
+
+    // the position and speed at the given (increasing) times, integrated with small Runge-Kutta steps
+    fn integrate(axis: &Axis, speed: f64, times: &[f64]) -> Vec<(f64, f64)> {
+        let acceleration = |speed: f64| axis.acceleration - axis.linear_drag * speed - axis.quadratic_drag * speed.abs() * speed;
+        let step: f64 = 1e-4;
+        let (mut time, mut position, mut speed) = (0.0, 0.0, speed);
+        let mut states = vec![];
+        for &end in times {
+            while time < end {
+                let h = step.min(end - time);
+                let k1 = acceleration(speed);
+                let k2 = acceleration(speed + h / 2.0 * k1);
+                let k3 = acceleration(speed + h / 2.0 * k2);
+                let k4 = acceleration(speed + h * k3);
+                position += h / 6.0 * (speed + 2.0 * (speed + h / 2.0 * k1) + 2.0 * (speed + h / 2.0 * k2) + speed + h * k3);
+                speed += h / 6.0 * (k1 + 2.0 * k2 + 2.0 * k3 + k4);
+                time += h;
+            }
+            states.push((position, speed));
+        }
+        states
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance * expected.abs().max(1.0), "{actual} != {expected}");
+    }
+
+    #[test]
+    fn advance_matches_numerical_integration() {
+        let times = [0.01, 0.1, 0.5, 1.0, 3.0, 8.0];
+        for axis in &AXES {
+            for speed in [-400.0, -25.0, 0.0, 10.0, 300.0] {
+                for (&time, (position, end_speed)) in times.iter().zip(integrate(axis, speed, &times)) {
+                    let (distance, speed) = axis.advance(speed, time);
+                    assert_close(distance, position, 1e-6);
+                    assert_close(speed, end_speed, 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stops_when_the_speed_reaches_zero() {
+        for axis in AXES.iter().filter(|axis| axis.has_drag()) {
+            for speed in [0.5, 30.0, 500.0] {
+                // only a speed against the acceleration reaches zero
+                let speed: f64 = if axis.acceleration > 0.0 { -speed } else { speed };
+                let direction = speed.signum();
+                let stop_time = axis.stop_time(direction * speed, direction * axis.acceleration);
+                if axis.acceleration == 0.0 {
+                    // drag alone only slows it down forever
+                    assert_eq!(stop_time, f64::INFINITY);
+                    assert!(axis.advance(speed, 10.0).1 * direction > 0.0);
+                    continue;
+                }
+                assert!(stop_time.is_finite());
+                assert!(axis.advance(speed, 0.999 * stop_time).1 * direction > 0.0);
+                assert!(axis.advance(speed, 1.001 * stop_time).1 * direction < 0.0);
+            }
+        }
+    }
+
+    // the first time at which the coordinate passes the limit, found by stepping
+    fn first_crossing(axis: &Axis, position: f64, speed: f64, target: f64, target_speed: f64, upper: bool) -> Option<f64> {
+        let step = 1e-4;
+        let sign = if upper { 1.0 } else { -1.0 };
+        (1..200_000).map(|i| i as f64 * step).find(|&time| sign * (position + axis.advance(speed, time).0 - target - target_speed * time) >= 0.0)
+    }
+
+    #[test]
+    fn reaches_and_meets_limits_like_stepping_does() {
+        for axis in &AXES {
+            for (position, speed, target, target_speed, upper) in [
+                (0.0, 50.0, 30.0, 0.0, true),
+                (0.0, -50.0, 30.0, 0.0, true),
+                (10.0, 200.0, -40.0, 0.0, false),
+                (10.0, -200.0, -40.0, 0.0, false),
+                (5.0, 0.0, 0.0, 0.0, false),
+                (0.0, 20.0, 30.0, -15.0, true),
+                (0.0, 80.0, 30.0, 40.0, true),
+                (0.0, -10.0, -30.0, 25.0, false),
+                (5.0, 0.0, 0.0, 60.0, false),
+            ] {
+                let expected = first_crossing(axis, position, speed, target, target_speed, upper);
+                let time = axis.time_to_meet(position, speed, target, target_speed, upper);
+                match (time, expected) {
+                    (Some(time), Some(expected)) => {
+                        assert!(time <= expected && time > expected - 2e-4, "{axis:?}: {time} != {expected}");
+                        assert_close(position + axis.advance(speed, time).0, target + target_speed * time, 1e-9);
+                    },
+                    (None, None) => (),
+                    // it can be reached after the stepping stops
+                    (Some(time), None) => assert!(time > 19.0, "{axis:?}: {time} wasn't expected"),
+                    (None, Some(expected)) => panic!("{axis:?}: {expected} was expected"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn finds_polynomial_roots_from_the_start() {
+        // (x + 3)(x - 1)(x - 2)(x - 5)
+        let coefficients = [-30.0, 41.0, -7.0, -5.0, 1.0];
+        let check = |start: f64, expected: &[f64]| {
+            let roots = polynomial_roots(&coefficients, start);
+            assert_eq!(roots.len(), expected.len(), "{roots:?}");
+            for (root, expected) in roots.iter().zip(expected) {
+                assert!((root - expected).abs() < 1e-12, "{root} != {expected}");
+            }
+        };
+        check(0.0, &[1.0, 2.0, 5.0]);
+        check(1.5, &[2.0, 5.0]);
+        check(-10.0, &[-3.0, 1.0, 2.0, 5.0]);
+        check(6.0, &[]);
+
+        assert_eq!(polynomial_roots(&[1.0, 0.0, 1.0], 0.0), Vec::<f64>::new());
+        assert_eq!(polynomial_roots(&[2.0, 0.0, 0.0], 0.0), Vec::<f64>::new());
+        assert_eq!(polynomial_roots(&[0.0, 4.0, 0.0], 0.0), vec![0.0]);
+    }
+}
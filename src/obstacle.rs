@@ -0,0 +1,507 @@
+use crate::ball::Ball;
+use crate::error::Error;
+use crate::motion::{Axis, time_to_distance};
+use crate::simulation::{CurvedCollision, search_collision};
+
+
+/// The shape of an [Obstacle].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// A straight line between two points, `width` thick with round ends
+    Segment {
+        /// One end
+        start: [f64; 2],
+        /// The other end
+        end: [f64; 2],
+        /// The thickness
+        width: f64,
+    },
+    /// A convex polygon
+    Polygon {
+        /// The corners in order (clockwise or counterclockwise)
+        points: Vec<[f64; 2]>,
+    },
+    /// A circle
+    Circle {
+        /// The center
+        center: [f64; 2],
+        /// The radius
+        radius: f64,
+    },
+}
+
+/// The way an [Obstacle] turns around a point, with a constant angular velocity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rotation {
+    /// The point it turns around
+    pub pivot: [f64; 2],
+    /// The angular velocity in radians per second (positive is counterclockwise, as y goes up)
+    pub angular_velocity: f64,
+}
+
+impl Rotation {
+    // the point that `point` is turned to after `time`
+    fn turn(&self, point: [f64; 2], time: f64) -> [f64; 2] {
+        let (sin, cos) = (self.angular_velocity * time).sin_cos();
+        let (dx, dy) = (point[0] - self.pivot[0], point[1] - self.pivot[1]);
+        [self.pivot[0] + dx * cos - dy * sin, self.pivot[1] + dx * sin + dy * cos]
+    }
+
+    // the velocity of the point of the turning obstacle at `point`
+    pub(crate) fn velocity(&self, point: [f64; 2]) -> [f64; 2] {
+        [-self.angular_velocity * (point[1] - self.pivot[1]), self.angular_velocity * (point[0] - self.pivot[0])]
+    }
+}
+
+/// An obstacle inside the box, balls bounce off it like off the walls.
+/// It stays where it is, unless it rotates, which it keeps doing however hard balls hit it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Obstacle {
+    /// The shape and position (at the start, if it rotates)
+    pub shape: Shape,
+    /// The color
+    pub color: [u8; 3],
+    /// If set, the obstacle turns around a point
+    pub rotation: Option<Rotation>,
+}
+
+impl Obstacle {
+    /// Checks that all coordinates are finite, the sizes aren't negative, polygons are convex
+    /// and that the pivot and angular velocity of a rotating obstacle are finite.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(rotation) = &self.rotation {
+            if !(rotation.pivot.iter().all(|c| c.is_finite()) && rotation.angular_velocity.is_finite()) {
+                return Err(Error::Validation(String::from("Pivot and angular velocity must be finite")));
+            }
+        }
+        match &self.shape {
+            Shape::Segment { start, end, width } => {
+                if !start.iter().chain(end).all(|c| c.is_finite()) {
+                    return Err(Error::Validation(String::from("Coordinates must be finite")));
+                }
+                if !(*width >= 0.0 && width.is_finite()) {
+                    return Err(Error::Validation(String::from("Width must be finite and not negative")));
+                }
+            },
+            Shape::Polygon { points } => {
+                if !points.iter().flatten().all(|c| c.is_finite()) {
+                    return Err(Error::Validation(String::from("Coordinates must be finite")));
+                }
+                if points.len() < 3 {
+                    return Err(Error::Validation(String::from("A polygon must have at least 3 points")));
+                }
+                // all corners have to turn the same way
+                let turns: Vec<f64> = (0..points.len()).map(|i| {
+                    let [a, b, c] = [0, 1, 2].map(|offset| points[(i + offset) % points.len()]);
+                    (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0])
+                }).collect();
+                if !(turns.iter().all(|&turn| turn > 0.0) || turns.iter().all(|&turn| turn < 0.0)) {
+                    return Err(Error::Validation(String::from("A polygon must be convex")));
+                }
+            },
+            Shape::Circle { center, radius } => {
+                if !center.iter().all(|c| c.is_finite()) {
+                    return Err(Error::Validation(String::from("Coordinates must be finite")));
+                }
+                if !(*radius > 0.0 && radius.is_finite()) {
+                    return Err(Error::Validation(String::from("Radius must be finite and greater than 0")));
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// The obstacle as its rotation has turned it after `time` (the same obstacle if it doesn't rotate).
+    pub fn at(&self, time: f64) -> Obstacle {
+        let Some(rotation) = self.rotation else {
+            return self.clone();
+        };
+        let shape = match &self.shape {
+            Shape::Segment { start, end, width } => Shape::Segment { start: rotation.turn(*start, time), end: rotation.turn(*end, time), width: *width },
+            Shape::Polygon { points } => Shape::Polygon { points: points.iter().map(|&point| rotation.turn(point, time)).collect() },
+            Shape::Circle { center, radius } => Shape::Circle { center: rotation.turn(*center, time), radius: *radius },
+        };
+        Obstacle { shape, ..*self }
+    }
+
+    /// Whether the ball overlaps the obstacle (just touching doesn't count).
+    pub fn overlaps(&self, ball: &Ball) -> bool {
+        self.signed_distance(ball.x, ball.y) < ball.r
+    }
+
+    // the distance of the point from the edge of the obstacle, negative inside it
+    pub(crate) fn signed_distance(&self, x: f64, y: f64) -> f64 {
+        match &self.shape {
+            Shape::Polygon { points } => {
+                // inside, the distance to the nearest edge is the distance to the nearest line through an edge
+                let outside = polygon_edges(points).map(|(start, end)| {
+                    let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+                    ((x - start[0]) * dy - (y - start[1]) * dx) / dx.hypot(dy)
+                }).fold(f64::NEG_INFINITY, f64::max);
+                if outside <= 0.0 {
+                    outside
+                } else {
+                    self.capsules().iter().map(|capsule| capsule.distance(x, y)).fold(f64::INFINITY, f64::min)
+                }
+            },
+            _ => self.capsules().iter().map(|capsule| capsule.distance(x, y) - capsule.radius).fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    // the smallest box (x_min, y_min, x_max, y_max) that contains the obstacle where it is (not everywhere it turns)
+    pub(crate) fn bounds(&self) -> [f64; 4] {
+        self.capsules().iter().map(|capsule| Capsule { rotation: None, ..*capsule }.bounds())
+            .fold([f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY], |a, b| [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])])
+    }
+
+    // the center and radius of the disc that a rotating obstacle sweeps (None if it doesn't rotate)
+    pub(crate) fn swept_disc(&self) -> Option<([f64; 2], f64)> {
+        let rotation = self.rotation?;
+        Some((rotation.pivot, self.capsules().iter().map(|capsule| capsule.reach(rotation.pivot)).fold(0.0, f64::max)))
+    }
+
+    // the parts of the obstacle that balls collide with (the inside of a polygon can't be reached without hitting its edges),
+    // they turn with the obstacle
+    pub(crate) fn capsules(&self) -> Vec<Capsule> {
+        let rotation = self.rotation;
+        match &self.shape {
+            Shape::Segment { start, end, width } => vec![Capsule { start: *start, end: *end, radius: width / 2.0, rotation }],
+            Shape::Polygon { points } => polygon_edges(points).map(|(start, end)| Capsule { start, end, radius: 0.0, rotation }).collect(),
+            Shape::Circle { center, radius } => vec![Capsule { start: *center, end: *center, radius: *radius, rotation }],
+        }
+    }
+}
+
+// the edges of a polygon, oriented so that the inside is on their left
+fn polygon_edges(points: &[[f64; 2]]) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+    let area: f64 = (0..points.len()).map(|i| {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        a[0] * b[1] - a[1] * b[0]
+    }).sum();
+    (0..points.len()).map(move |i| {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        if area >= 0.0 { (a, b) } else { (b, a) }
+    })
+}
+
+/// A line segment with round ends that balls can't get closer to than `radius`,
+/// every obstacle consists of these (a circle is a segment of length 0).
+/// The ends of a rotating one are where they are at the start.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Capsule {
+    pub start: [f64; 2],
+    pub end: [f64; 2],
+    pub radius: f64,
+    pub rotation: Option<Rotation>,
+}
+
+impl Capsule {
+    /// The capsule as its rotation has turned it after `time`.
+    pub fn at(&self, time: f64) -> Capsule {
+        match self.rotation {
+            Some(rotation) => Capsule { start: rotation.turn(self.start, time), end: rotation.turn(self.end, time), ..*self },
+            None => *self,
+        }
+    }
+
+    /// The velocity of the capsule at `point` (0 if it doesn't rotate).
+    pub fn velocity(&self, point: [f64; 2]) -> [f64; 2] {
+        self.rotation.map_or([0.0, 0.0], |rotation| rotation.velocity(point))
+    }
+
+    // how far the capsule reaches from `point`
+    fn reach(&self, point: [f64; 2]) -> f64 {
+        (self.start[0] - point[0]).hypot(self.start[1] - point[1]).max((self.end[0] - point[0]).hypot(self.end[1] - point[1])) + self.radius
+    }
+
+    // the point of the segment that is closest to (x, y)
+    fn closest(&self, x: f64, y: f64) -> [f64; 2] {
+        let (dx, dy) = (self.end[0] - self.start[0], self.end[1] - self.start[1]);
+        let length_squared = dx * dx + dy * dy;
+        let share = if length_squared > 0.0 { (((x - self.start[0]) * dx + (y - self.start[1]) * dy) / length_squared).clamp(0.0, 1.0) } else { 0.0 };
+        [self.start[0] + share * dx, self.start[1] + share * dy]
+    }
+
+    // the distance of (x, y) from the segment
+    fn distance(&self, x: f64, y: f64) -> f64 {
+        let closest = self.closest(x, y);
+        (x - closest[0]).hypot(y - closest[1])
+    }
+
+    /// The distance between the edges of the capsule and a ball (negative if they overlap).
+    pub fn gap(&self, ball: &Ball) -> f64 {
+        self.distance(ball.x, ball.y) - self.radius - ball.r
+    }
+
+    /// The direction from the capsule to the center of the ball (a unit vector).
+    pub fn normal(&self, ball: &Ball) -> [f64; 2] {
+        let closest = self.closest(ball.x, ball.y);
+        let (dx, dy) = (ball.x - closest[0], ball.y - closest[1]);
+        let distance = dx.hypot(dy);
+        if distance > 0.0 {
+            [dx / distance, dy / distance]
+        } else {
+            // the center is on the segment, any direction away from it will do
+            let (dx, dy) = (self.end[0] - self.start[0], self.end[1] - self.start[1]);
+            let length = dx.hypot(dy);
+            if length > 0.0 { [-dy / length, dx / length] } else { [0.0, 1.0] }
+        }
+    }
+
+    /// The smallest box (x_min, y_min, x_max, y_max) that contains the capsule, wherever it turns if it rotates.
+    pub fn bounds(&self) -> [f64; 4] {
+        if let Some(rotation) = self.rotation {
+            let reach = self.reach(rotation.pivot);
+            let [x, y] = rotation.pivot;
+            return [x - reach, y - reach, x + reach, y + reach];
+        }
+        [
+            self.start[0].min(self.end[0]) - self.radius,
+            self.start[1].min(self.end[1]) - self.radius,
+            self.start[0].max(self.end[0]) + self.radius,
+            self.start[1].max(self.end[1]) + self.radius,
+        ]
+    }
+
+    /// Returns the time after which the ball will hit the capsule, if it ever does, starting at `time`.
+    /// For a capsule that doesn't rotate it is exact without drag, otherwise the search is stopped after `max_steps`.
+    pub fn time_to_hit(&self, ball: &Ball, axes: [Axis; 2], max_steps: usize, time: f64) -> Option<CurvedCollision> {
+        if let Some(rotation) = self.rotation {
+            self.rotating_search(ball, axes, max_steps, time, rotation)
+        } else if axes.iter().any(Axis::has_drag) {
+            self.search(ball, axes, max_steps)
+        } else {
+            self.exact_time_to_hit(ball, [axes[0].acceleration, axes[1].acceleration]).map(CurvedCollision::At)
+        }
+    }
+
+    // the collision time of a ball moving along a parabola (or a straight line without gravity)
+    fn exact_time_to_hit(&self, ball: &Ball, gravity: [f64; 2]) -> Option<f64> {
+        let distance = ball.r + self.radius;
+        let position = |time: f64| [ball.x + (ball.v_x + gravity[0] * time / 2.0) * time, ball.y + (ball.v_y + gravity[1] * time / 2.0) * time];
+        let mut first: Option<f64> = None;
+
+        // either side of the segment, which only counts if the ball hits it between the ends
+        let (dx, dy) = (self.end[0] - self.start[0], self.end[1] - self.start[1]);
+        let length = dx.hypot(dy);
+        if length > 0.0 {
+            let (ux, uy) = (dx / length, dy / length);
+            for (nx, ny) in [(-uy, ux), (uy, -ux)] {
+                let offset = nx * (ball.x - self.start[0]) + ny * (ball.y - self.start[1]);
+                if offset < 0.0 {
+                    // the ball is on the other side
+                    continue;
+                }
+                let axis = Axis { acceleration: nx * gravity[0] + ny * gravity[1], linear_drag: 0.0, quadratic_drag: 0.0 };
+                if let Some(time) = axis.time_to_reach(offset, nx * ball.v_x + ny * ball.v_y, distance, false) {
+                    let [x, y] = position(time);
+                    let along = ux * (x - self.start[0]) + uy * (y - self.start[1]);
+                    if (0.0..=length).contains(&along) {
+                        first = Some(first.map_or(time, |first| first.min(time)));
+                    }
+                }
+            }
+        }
+
+        // the round ends
+        let ends = if length > 0.0 { vec![self.start, self.end] } else { vec![self.start] };
+        for point in ends {
+            let position = [ball.x - point[0], ball.y - point[1]];
+            if let Some(time) = time_to_distance(position, [ball.v_x, ball.v_y], gravity, distance, true) {
+                first = Some(first.map_or(time, |first| first.min(time)));
+            }
+        }
+        first
+    }
+
+    // the collision time of a ball whose path is curved by drag with a capsule that doesn't rotate
+    fn search(&self, ball: &Ball, axes: [Axis; 2], max_steps: usize) -> Option<CurvedCollision> {
+        let tolerance = (ball.r + self.radius) * 1e-9;
+        // along each axis the velocity approaches the terminal velocity, so neither it nor the acceleration ever get bigger
+        let max_speed = axes[0].terminal_speed().abs().max(ball.v_x.abs()).hypot(axes[1].terminal_speed().abs().max(ball.v_y.abs()));
+        let max_acceleration = axes[0].acceleration_at(ball.v_x).hypot(axes[1].acceleration_at(ball.v_y));
+        // how fast the gap can change its rate, from the acceleration and from the direction to the capsule turning
+        let max_curvature = max_acceleration + max_speed * max_speed / (ball.r + self.radius);
+
+        search_collision(ball, axes, max_steps, tolerance, true, |moved, _| (self.gap(moved), self.normal(moved), [0.0, 0.0]), |_, gap, gap_speed| {
+            2.0 * gap / (-gap_speed + (gap_speed * gap_speed + 2.0 * max_curvature * gap).sqrt())
+        })
+    }
+
+    // the collision time of a ball with a rotating capsule, which stands still when seen from a frame that turns with it,
+    // where the ball moves along a curve
+    fn rotating_search(&self, ball: &Ball, axes: [Axis; 2], max_steps: usize, time: f64, rotation: Rotation) -> Option<CurvedCollision> {
+        let tolerance = (ball.r + self.radius) * 1e-9;
+        // nothing can happen before the ball gets into the disc that the capsule sweeps
+        let disc = Capsule { start: rotation.pivot, end: rotation.pivot, radius: self.reach(rotation.pivot), rotation: None };
+        if disc.gap(ball) > tolerance {
+            match disc.time_to_hit(ball, axes, max_steps, time)? {
+                CurvedCollision::At(entry) | CurvedCollision::NotBefore(entry) if entry > 0.0 => return Some(CurvedCollision::NotBefore(entry)),
+                _ => {},
+            }
+        }
+
+        // along each axis the acceleration never gets bigger (see Capsule::search)
+        let max_acceleration = axes[0].acceleration_at(ball.v_x).hypot(axes[1].acceleration_at(ball.v_y));
+        let angular_speed = rotation.angular_velocity.abs();
+        // how fast the gap can change its rate during the next `horizon`: in the turning frame the ball is accelerated by gravity and drag,
+        // and by the centrifugal and Coriolis forces, and the direction from the capsule to it turns as it moves
+        let max_curvature = |moved: &Ball, horizon: f64| {
+            let speed = moved.v_x.hypot(moved.v_y) + max_acceleration * horizon;
+            let distance = (moved.x - rotation.pivot[0]).hypot(moved.y - rotation.pivot[1]) + speed * horizon;
+            let frame_speed = speed + angular_speed * distance;
+            let frame_acceleration = max_acceleration + 2.0 * angular_speed * frame_speed + angular_speed * angular_speed * distance;
+            frame_acceleration + frame_speed * frame_speed / (ball.r + self.radius)
+        };
+
+        search_collision(ball, axes, max_steps, tolerance, false, |moved, moved_time| {
+            let capsule = self.at(time + moved_time);
+            let normal = capsule.normal(moved);
+            let contact = [moved.x - normal[0] * moved.r, moved.y - normal[1] * moved.r];
+            (capsule.gap(moved), normal, rotation.velocity(contact))
+        }, |moved, gap, gap_speed| {
+            let step = |curvature: f64| 2.0 * gap / (-gap_speed + (gap_speed * gap_speed + 2.0 * curvature * gap).sqrt());
+            // the bound for as long as a first guess holds until then, and the gap surely stays open for the shorter step it gives
+            let guess = step(max_curvature(moved, 0.0));
+            step(max_curvature(moved, guess))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STILL: Axis = Axis { acceleration: 0.0, linear_drag: 0.0, quadratic_drag: 0.0 };
+
+    // a horizontal segment from (100, 100) to (300, 100), 10 thick
+    fn segment() -> Capsule {
+        Capsule { start: [100.0, 100.0], end: [300.0, 100.0], radius: 5.0, rotation: None }
+    }
+
+    // the time at which the ball hits the capsule, from a search that isn't stopped
+    fn hit(capsule: &Capsule, ball: &Ball, axes: [Axis; 2]) -> Option<f64> {
+        match capsule.time_to_hit(ball, axes, 1_000_000, 0.0)? {
+            CurvedCollision::At(time) => Some(time),
+            CurvedCollision::NotBefore(time) => panic!("the search stopped at {time}"),
+        }
+    }
+
+    // the ball moved along its path for `time`
+    fn moved(ball: &Ball, axes: [Axis; 2], time: f64) -> Ball {
+        let ((dx, v_x), (dy, v_y)) = (axes[0].advance(ball.v_x, time), axes[1].advance(ball.v_y, time));
+        Ball { x: ball.x + dx, y: ball.y + dy, v_x, v_y, ..ball.clone() }
+    }
+
+    // checks that the ball touches the capsule at `time`, and doesn't before
+    fn assert_first_touch(capsule: &Capsule, ball: &Ball, axes: [Axis; 2], time: f64) {
+        assert!(capsule.gap(&moved(ball, axes, time)).abs() < 1e-6, "gap {} at {time}", capsule.gap(&moved(ball, axes, time)));
+        for i in 0..1000 {
+            let before = time * i as f64 / 1000.0;
+            assert!(capsule.gap(&moved(ball, axes, before)) > 0.0, "touching at {before} before {time}");
+        }
+    }
+
+    #[test]
+    fn hits_the_side_of_a_segment() {
+        let ball = Ball::new(200.0, 200.0, 1.0, 10.0, 30.0, -100.0, [0; 3]);
+        let time = hit(&segment(), &ball, [STILL; 2]).unwrap();
+        assert!((time - 0.85).abs() < 1e-12);
+        assert_first_touch(&segment(), &ball, [STILL; 2], time);
+
+        // from below, falling away from it
+        let gravity = [STILL, Axis { acceleration: -100.0, ..STILL }];
+        let ball = Ball::new(200.0, 50.0, 1.0, 10.0, 0.0, 60.0, [0; 3]);
+        assert_eq!(hit(&segment(), &ball, gravity), None);
+        // from above, dropped onto it
+        let ball = Ball::new(150.0, 300.0, 1.0, 10.0, 0.0, 0.0, [0; 3]);
+        let time = hit(&segment(), &ball, gravity).unwrap();
+        assert!((time - 3.7_f64.sqrt()).abs() < 1e-12);
+        assert_first_touch(&segment(), &ball, gravity, time);
+    }
+
+    #[test]
+    fn hits_the_round_ends_of_a_capsule() {
+        let ball = Ball::new(400.0, 100.0, 1.0, 10.0, -50.0, 0.0, [0; 3]);
+        let time = hit(&segment(), &ball, [STILL; 2]).unwrap();
+        assert!((time - 1.7).abs() < 1e-12);
+
+        // at an angle straight towards the ends
+        for (ball, expected) in [(Ball::new(360.0, 180.0, 1.0, 10.0, -60.0, -80.0, [0; 3]), 0.85), (Ball::new(70.0, 60.0, 1.0, 10.0, 30.0, 40.0, [0; 3]), 0.7)] {
+            let time = hit(&segment(), &ball, [STILL; 2]).unwrap();
+            assert!((time - expected).abs() < 1e-12, "{time} != {expected}");
+            assert_first_touch(&segment(), &ball, [STILL; 2], time);
+        }
+
+        // a circle
+        let circle = Capsule { start: [0.0, 0.0], end: [0.0, 0.0], radius: 20.0, rotation: None };
+        let ball = Ball::new(100.0, 18.0, 1.0, 10.0, -100.0, 0.0, [0; 3]);
+        let time = hit(&circle, &ball, [STILL; 2]).unwrap();
+        assert!((time - 0.76).abs() < 1e-12);
+    }
+
+    #[test]
+    fn lets_grazing_balls_pass() {
+        // passing the end 0.01 farther away than touching distance, and 0.01 closer
+        for (y, hits) in [(115.01, false), (114.99, true), (84.99, false), (85.01, true)] {
+            let ball = Ball::new(500.0, y, 1.0, 10.0, -100.0, 0.0, [0; 3]);
+            let time = hit(&segment(), &ball, [STILL; 2]);
+            assert_eq!(time.is_some(), hits, "{y}");
+            if let Some(time) = time {
+                assert_first_touch(&segment(), &ball, [STILL; 2], time);
+            }
+        }
+        // moving away from it
+        let ball = Ball::new(200.0, 200.0, 1.0, 10.0, 0.0, 1.0, [0; 3]);
+        assert_eq!(hit(&segment(), &ball, [STILL; 2]), None);
+        // parallel to it
+        let ball = Ball::new(0.0, 115.5, 1.0, 10.0, 100.0, 0.0, [0; 3]);
+        assert_eq!(hit(&segment(), &ball, [STILL; 2]), None);
+    }
+
+    #[test]
+    fn searches_curved_paths_with_drag() {
+        let drag = Axis { acceleration: 0.0, linear_drag: 0.5, quadratic_drag: 0.001 };
+        let ball = Ball::new(380.0, 180.0, 1.0, 10.0, -120.0, -90.0, [0; 3]);
+        let time = hit(&segment(), &ball, [drag; 2]).unwrap();
+        assert_first_touch(&segment(), &ball, [drag; 2], time);
+        // drag stops it before it gets there
+        let ball = Ball::new(200.0, 300.0, 1.0, 10.0, 0.0, -50.0, [0; 3]);
+        assert_eq!(hit(&segment(), &ball, [drag; 2]), None);
+    }
+
+    #[test]
+    fn hits_rotating_capsules() {
+        // a thin bar turning around its middle sweeps over a ball at rest when its distance from it is the ball's radius
+        let bar = Capsule { start: [-100.0, 0.0], end: [100.0, 0.0], radius: 0.0, rotation: Some(Rotation { pivot: [0.0, 0.0], angular_velocity: 1.0 }) };
+        let ball = Ball::new(0.0, 50.0, 1.0, 10.0, 0.0, 0.0, [0; 3]);
+        let time = hit(&bar, &ball, [STILL; 2]).unwrap();
+        assert!((time - 0.2_f64.acos()).abs() < 1e-6, "{time}");
+        assert!(bar.at(time).gap(&ball).abs() < 1e-6);
+        // out of its reach
+        let ball = Ball::new(0.0, 120.0, 1.0, 10.0, 0.0, 0.0, [0; 3]);
+        assert_eq!(hit(&bar, &ball, [STILL; 2]), None);
+    }
+
+    #[test]
+    fn measures_the_distance_from_obstacles() {
+        let square = Obstacle { shape: Shape::Polygon { points: vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]] }, color: [0; 3], rotation: None };
+        assert_eq!(square.signed_distance(5.0, 5.0), -5.0);
+        assert_eq!(square.signed_distance(8.0, 5.0), -2.0);
+        assert_eq!(square.signed_distance(15.0, 5.0), 5.0);
+        assert_eq!(square.signed_distance(13.0, 14.0), 5.0);
+        // the order of the corners doesn't matter
+        let clockwise = Obstacle { shape: Shape::Polygon { points: vec![[0.0, 0.0], [0.0, 10.0], [10.0, 10.0], [10.0, 0.0]] }, color: [0; 3], rotation: None };
+        assert_eq!(clockwise.signed_distance(8.0, 5.0), -2.0);
+        assert_eq!(clockwise.signed_distance(-3.0, -4.0), 5.0);
+
+        let segment = Obstacle { shape: Shape::Segment { start: [0.0, 0.0], end: [10.0, 0.0], width: 2.0 }, color: [0; 3], rotation: None };
+        assert_eq!(segment.signed_distance(5.0, 3.0), 2.0);
+        assert_eq!(segment.signed_distance(5.0, 0.5), -0.5);
+        assert_eq!(segment.signed_distance(14.0, -3.0), 4.0);
+        let circle = Obstacle { shape: Shape::Circle { center: [1.0, 1.0], radius: 3.0 }, color: [0; 3], rotation: None };
+        assert_eq!(circle.signed_distance(4.0, 5.0), 2.0);
+        assert!(circle.overlaps(&Ball::new(4.0, 5.0, 1.0, 2.5, 0.0, 0.0, [0; 3])));
+        assert!(!circle.overlaps(&Ball::new(4.0, 5.0, 1.0, 2.0, 0.0, 0.0, [0; 3])));
+    }
+}
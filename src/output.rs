@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use tinydraw::ImageRGB8;
+
+use crate::error::Error;
+
+
+/// The kind of output the rendered frames are written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A video file encoded by ffmpeg
+    Video,
+    /// A directory of numbered PNG images
+    Png,
+    /// A directory of numbered PPM images
+    Ppm,
+    /// Raw rgb24 frames written one after another (to stdout if the destination is `-`)
+    Raw,
+}
+
+impl OutputFormat {
+    /// Guesses the format from the destination.
+    /// `-` means raw frames to stdout, a directory (existing or ending with a separator) means PNG images, anything else is a video.
+    pub fn detect(destination: &Path) -> Self {
+        let destination_str = destination.to_string_lossy();
+        if destination_str == "-" {
+            Self::Raw
+        } else if destination.is_dir() || destination_str.ends_with('/') || destination_str.ends_with(std::path::MAIN_SEPARATOR) {
+            Self::Png
+        } else {
+            Self::Video
+        }
+    }
+}
+
+/// A video codec supported by [FfmpegSink].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// H.264 (libx264)
+    X264,
+    /// H.265/HEVC (libx265)
+    X265,
+    /// VP9 (libvpx-vp9)
+    Vp9,
+    /// AV1 (libsvtav1)
+    Av1,
+    /// Apple ProRes (prores_ks)
+    ProRes,
+}
+
+impl Codec {
+    /// The name of the ffmpeg encoder
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            Self::X264 => "libx264",
+            Self::X265 => "libx265",
+            Self::Vp9 => "libvpx-vp9",
+            Self::Av1 => "libsvtav1",
+            Self::ProRes => "prores_ks",
+        }
+    }
+}
+
+/// Settings passed to the ffmpeg encoder.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncoderSettings {
+    /// The video codec
+    pub codec: Codec,
+    /// Constant rate factor (quality), lower is better.
+    /// If neither CRF nor bitrate are set, x264 encodes losslessly (CRF 0) and other codecs use ffmpeg defaults.
+    pub crf: Option<u32>,
+    /// Target bitrate (e.g. `5M`)
+    pub bitrate: Option<String>,
+    /// Encoder speed/quality preset (e.g. `slow` for x264/x265, `6` for AV1)
+    pub preset: Option<String>,
+    /// Pixel format of the video (e.g. `yuv420p` for compatibility with browsers)
+    pub pixel_format: Option<String>,
+    /// Additional arguments for ffmpeg, placed just before the output file
+    pub extra_args: Vec<String>,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self { codec: Codec::X264, crf: None, bitrate: None, preset: None, pixel_format: None, extra_args: vec![] }
+    }
+}
+
+impl EncoderSettings {
+    /// Returns the ffmpeg output arguments for these settings, or [Error::Validation] if they don't fit the codec or the video size.
+    pub fn to_args(&self, width: usize, height: usize) -> Result<Vec<String>, Error> {
+        let mut args: Vec<String> = vec![String::from("-c:v"), String::from(self.codec.encoder())];
+
+        match (self.crf, self.codec) {
+            (Some(_), Codec::ProRes) => return Err(Error::Validation(String::from("ProRes doesn't support CRF"))),
+            (Some(crf), _) => args.extend([String::from("-crf"), crf.to_string()]),
+            (None, Codec::X264) if self.bitrate.is_none() => args.extend([String::from("-crf"), String::from("0")]),  // lossless
+            (None, _) => {},
+        }
+        match (&self.bitrate, self.codec) {
+            (Some(bitrate), _) => args.extend([String::from("-b:v"), bitrate.clone()]),
+            (None, Codec::Vp9) if self.crf.is_some() => args.extend([String::from("-b:v"), String::from("0")]),  // constant quality mode
+            (None, _) => {},
+        }
+        if let Some(preset) = &self.preset {
+            match self.codec {
+                Codec::X264 | Codec::X265 | Codec::Av1 => args.extend([String::from("-preset"), preset.clone()]),
+                Codec::Vp9 | Codec::ProRes => return Err(Error::Validation(format!("{:?} doesn't support presets", self.codec))),
+            }
+        }
+        if let Some(pixel_format) = &self.pixel_format {
+            if (pixel_format.starts_with("yuv420") || pixel_format.starts_with("nv12")) && (!width.is_multiple_of(2) || !height.is_multiple_of(2)) {
+                return Err(Error::Validation(format!("Pixel format {} needs even width and height", pixel_format)));
+            }
+            args.extend([String::from("-pix_fmt"), pixel_format.clone()]);
+        }
+        args.extend(self.extra_args.iter().cloned());
+
+        Ok(args)
+    }
+}
+
+/// A destination for rendered frames.
+pub trait FrameSink {
+    /// Writes the next frame.
+    fn write_frame(&mut self, img: &ImageRGB8) -> Result<(), Error>;
+
+    /// Flushes everything and closes the output. No frames should be written after this.
+    fn finish(&mut self) -> Result<(), Error>;
+}
+
+/// The number of last lines of ffmpeg output that are kept for error messages
+const FFMPEG_LOG_LINES: usize = 20;
+
+/// Pipes frames into an ffmpeg process, which encodes them into a video file.
+/// The last lines ffmpeg writes to stderr are kept, and reported if it fails.
+pub struct FfmpegSink {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    log: Arc<Mutex<VecDeque<String>>>,
+    log_reader: Option<JoinHandle<()>>,
+}
+
+impl FfmpegSink {
+    /// Starts ffmpeg (`ffmpeg_path`) encoding `width`×`height` frames at `fps` into `destination`.
+    pub fn new(ffmpeg_path: &Path, destination: &Path, width: usize, height: usize, fps: u128, encoder: &EncoderSettings) -> Result<Self, Error> {
+        let encoder_args = encoder.to_args(width, height)?;
+        let mut child = Command::new(ffmpeg_path)
+            .arg("-hide_banner").arg("-nostats") // only log useful information
+            .arg("-y") // overwrite file if it already exists
+            .arg("-f").arg("rawvideo") // interpret the information from stdin as "raw video"
+            .arg("-pix_fmt").arg("rgb24") // every three bytes are [r, g, b] pixel
+            .arg("-s").arg(format!("{}x{}", width, height)) // the size of the video
+            .arg("-r").arg(fps.to_string()) // the fps of the video
+            .arg("-an") // don't use audio
+            .arg("-i").arg("-") // get data from stdin
+            .args(encoder_args) // codec, quality, ...
+            .arg(destination) // output file
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()) // set stdin and stderr to be piped
+            .spawn() // Run the child command
+            .map_err(|err| Error::Encoder(format!("Can't start ffmpeg ({}): {}", ffmpeg_path.display(), err)))?;
+        let stdin = child.stdin.take();
+
+        let log = Arc::new(Mutex::new(VecDeque::with_capacity(FFMPEG_LOG_LINES)));
+        let log_reader = child.stderr.take().map(|stderr| {
+            let log = Arc::clone(&log);
+            thread::spawn(move || read_log(stderr, &log))
+        });
+
+        Ok(Self { child, stdin, log, log_reader })
+    }
+
+    /// Waits for ffmpeg to exit, returns [Err] with its last lines of output if it failed.
+    fn wait(&mut self) -> Result<(), Error> {
+        drop(self.stdin.take()); // closing stdin tells ffmpeg there are no more frames
+        let status = self.child.wait().map_err(|err| Error::Encoder(format!("Can't wait for ffmpeg: {}", err)))?;
+        if let Some(log_reader) = self.log_reader.take() {
+            let _ = log_reader.join();
+        }
+
+        if status.success() {
+            Ok(())
+        } else {
+            let log = self.log.lock().unwrap();
+            let mut message = format!("ffmpeg failed ({})", status);
+            for line in log.iter() {
+                message.push_str("\n    ");
+                message.push_str(line);
+            }
+            Err(Error::Encoder(message))
+        }
+    }
+}
+
+// keeps the last lines of ffmpeg output in the ring buffer
+fn read_log<R: Read>(stderr: R, log: &Mutex<VecDeque<String>>) {
+    for line in BufReader::new(stderr).split(b'\n') {
+        let Ok(line) = line else { break };
+        // progress updates overwrite the line with \r, only the last one matters
+        let line = line.rsplit(|&c| c == b'\r').find(|part| !part.is_empty()).unwrap_or(&[]);
+        let line = String::from_utf8_lossy(line).trim_end().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        let mut log = log.lock().unwrap();
+        if log.len() == FFMPEG_LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(line);
+    }
+}
+
+impl FrameSink for FfmpegSink {
+    fn write_frame(&mut self, img: &ImageRGB8) -> Result<(), Error> {
+        let result = match &mut self.stdin {
+            Some(stdin) => stdin.write_all(img.to_bytes()),
+            None => return Err(Error::Encoder(String::from("ffmpeg is already finished"))),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            // ffmpeg most likely exited, report why
+            Err(err) => match self.wait() {
+                Ok(()) => Err(Error::Encoder(format!("Can't write frame to ffmpeg: {}", err))),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.wait()
+    }
+}
+
+/// Saves every frame as a numbered image (`frame_000000.png`, `frame_000001.png`, ...) in a directory.
+pub struct ImageSequenceSink {
+    directory: PathBuf,
+    format: OutputFormat,
+    frame: usize,
+}
+
+impl ImageSequenceSink {
+    /// Creates `directory` (if needed) to save the frames in.
+    /// `format` has to be [OutputFormat::Png] or [OutputFormat::Ppm].
+    pub fn new(directory: &Path, format: OutputFormat) -> Result<Self, Error> {
+        if !matches!(format, OutputFormat::Png | OutputFormat::Ppm) {
+            return Err(Error::Validation(format!("{:?} is not an image format", format)));
+        }
+        fs::create_dir_all(directory).map_err(|err| Error::io(format!("Can't create directory {}", directory.display()), err))?;
+        Ok(Self { directory: directory.to_path_buf(), format, frame: 0 })
+    }
+}
+
+impl FrameSink for ImageSequenceSink {
+    fn write_frame(&mut self, img: &ImageRGB8) -> Result<(), Error> {
+        let extension = if self.format == OutputFormat::Png { "png" } else { "ppm" };
+        let path = self.directory.join(format!("frame_{:06}.{}", self.frame, extension));
+        self.frame += 1;
+
+        if self.format == OutputFormat::Png {
+            img.to_png(&path.to_string_lossy()).map_err(|err| Error::io(format!("Can't save {}", path.display()), io::Error::other(err)))
+        } else {
+            let mut file = File::create(&path).map(BufWriter::new).map_err(|err| Error::io(format!("Can't create {}", path.display()), err))?;
+            write!(file, "P6\n{} {}\n255\n", img.width, img.height)
+                .and_then(|_| file.write_all(img.to_bytes()))
+                .and_then(|_| file.flush())
+                .map_err(|err| Error::io(format!("Can't save {}", path.display()), err))
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Writes raw rgb24 frames one after another (e.g. to stdout, for piping into other tools).
+pub struct RawSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> RawSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> FrameSink for RawSink<W> {
+    fn write_frame(&mut self, img: &ImageRGB8) -> Result<(), Error> {
+        self.writer.write_all(img.to_bytes()).map_err(|err| Error::io("Can't write frame", err))
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush().map_err(|err| Error::io("Can't write frame", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_format_from_the_destination() {
+        assert_eq!(OutputFormat::detect(Path::new("-")), OutputFormat::Raw);
+        for video in ["video.mp4", "out/video.webm", "clip.MOV", "no_extension"] {
+            assert_eq!(OutputFormat::detect(Path::new(video)), OutputFormat::Video, "{video}");
+        }
+        for directory in ["frames/", "out/frames/"] {
+            assert_eq!(OutputFormat::detect(Path::new(directory)), OutputFormat::Png, "{directory}");
+        }
+        // an existing directory, even with a video extension
+        let directory = std::env::temp_dir().join(format!("circle-bounce-frames-{}.mp4", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let format = OutputFormat::detect(&directory);
+        fs::remove_dir(&directory).unwrap();
+        assert_eq!(format, OutputFormat::Png);
+    }
+
+    #[test]
+    fn writes_raw_frames() {
+        let mut img = ImageRGB8::new(2, 1, [1, 2, 3]);
+        let mut sink = RawSink::new(vec![]);
+        sink.write_frame(&img).unwrap();
+        img.image_data[1] = [4, 5, 6];
+        sink.write_frame(&img).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(sink.writer, [1, 2, 3, 1, 2, 3, 1, 2, 3, 4, 5, 6]);
+        assert!(matches!(ImageSequenceSink::new(Path::new("frames"), OutputFormat::Raw), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn passes_encoder_settings_to_ffmpeg() {
+        let settings = |codec, crf, preset: Option<&str>| EncoderSettings { codec, crf, preset: preset.map(String::from), ..EncoderSettings::default() };
+        for (settings, expected) in [
+            (settings(Codec::X264, None, None), "-c:v libx264 -crf 0"),
+            (settings(Codec::X264, Some(23), Some("slow")), "-c:v libx264 -crf 23 -preset slow"),
+            (EncoderSettings { bitrate: Some(String::from("5M")), ..EncoderSettings::default() }, "-c:v libx264 -b:v 5M"),
+            (settings(Codec::X265, None, None), "-c:v libx265"),
+            (settings(Codec::X265, Some(28), Some("medium")), "-c:v libx265 -crf 28 -preset medium"),
+            (settings(Codec::Vp9, None, None), "-c:v libvpx-vp9"),
+            (settings(Codec::Vp9, Some(31), None), "-c:v libvpx-vp9 -crf 31 -b:v 0"),
+            (EncoderSettings { bitrate: Some(String::from("2M")), ..settings(Codec::Vp9, Some(31), None) }, "-c:v libvpx-vp9 -crf 31 -b:v 2M"),
+            (settings(Codec::Av1, None, Some("6")), "-c:v libsvtav1 -preset 6"),
+            (settings(Codec::Av1, Some(35), Some("6")), "-c:v libsvtav1 -crf 35 -preset 6"),
+            (settings(Codec::ProRes, None, None), "-c:v prores_ks"),
+            (EncoderSettings { pixel_format: Some(String::from("yuv420p")), extra_args: vec![String::from("-tune"), String::from("animation")], ..settings(Codec::X264, Some(18), None) },
+                "-c:v libx264 -crf 18 -pix_fmt yuv420p -tune animation"),
+        ] {
+            assert_eq!(settings.to_args(1920, 1080).unwrap().join(" "), expected);
+        }
+
+        for (settings, width) in [
+            (settings(Codec::ProRes, Some(20), None), 1920),
+            (settings(Codec::ProRes, None, Some("fast")), 1920),
+            (settings(Codec::Vp9, Some(31), Some("good")), 1920),
+            (EncoderSettings { pixel_format: Some(String::from("yuv420p")), ..EncoderSettings::default() }, 1921),
+            (EncoderSettings { pixel_format: Some(String::from("nv12")), ..EncoderSettings::default() }, 1921),
+        ] {
+            assert!(matches!(settings.to_args(width, 1080), Err(Error::Validation(_))), "{settings:?}");
+        }
+        // only subsampled formats need even sizes
+        let settings = EncoderSettings { pixel_format: Some(String::from("yuv444p")), ..EncoderSettings::default() };
+        assert_eq!(settings.to_args(1921, 1081).unwrap().join(" "), "-c:v libx264 -crf 0 -pix_fmt yuv444p");
+    }
+
+    #[test]
+    fn keeps_the_last_lines_of_ffmpeg_output() {
+        let output: String = (1..=30).map(|i| format!("line {i}\n")).collect();
+        // progress updates overwritten with \r and empty lines
+        let output = format!("{output}frame=1\rframe=2\r\n\n\r\n");
+        let log = Mutex::new(VecDeque::new());
+        read_log(output.as_bytes(), &log);
+        let expected: Vec<String> = (12..=30).map(|i| format!("line {i}")).chain([String::from("frame=2")]).collect();
+        assert_eq!(Vec::from(log.into_inner().unwrap()), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_ffmpeg_failures_with_its_last_lines() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // a stand-in for ffmpeg that complains a lot and fails
+        let path = std::env::temp_dir().join(format!("circle-bounce-ffmpeg-{}", std::process::id()));
+        fs::write(&path, "#!/bin/sh\ni=1\nwhile [ $i -le 30 ]; do echo \"line $i\" >&2; i=$((i + 1)); done\nexit 3\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let result = FfmpegSink::new(&path, Path::new("video.mp4"), 4, 4, 60, &EncoderSettings::default()).and_then(|mut sink| sink.finish());
+        fs::remove_file(&path).unwrap();
+
+        let Err(Error::Encoder(message)) = result else { panic!("{:?} isn't an encoder error", result.err()) };
+        let lines: Vec<&str> = message.lines().collect();
+        assert!(lines[0].starts_with("ffmpeg failed"), "{message}");
+        assert_eq!(lines.len(), 1 + FFMPEG_LOG_LINES);
+        assert_eq!(lines[1], "    line 11");
+        assert_eq!(lines[FFMPEG_LOG_LINES], "    line 30");
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tinydraw::ImageRGB8;
+
+use crate::arena::Arena;
+use crate::ball::Ball;
+use crate::error::Error;
+use crate::obstacle::Obstacle;
+use crate::output::FrameSink;
+use crate::render::{RenderMode, generate_frame};
+use crate::simulation::Simulation;
+
+
+// the balls, the positions of the walls and the time at one moment
+type Snapshot = (Vec<Ball>, [f64; 4], f64);
+
+/// Settings for rendering a simulation into a [FrameSink] with [render_simulation].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// The number of frames to render
+    pub num_of_frames: usize,
+    /// The frames per second (the simulation advances by `1 / fps` seconds between frames)
+    pub fps: u128,
+    /// The background color
+    pub background_color: [u8; 3],
+    /// The way the balls are drawn
+    pub mode: RenderMode,
+    /// The number of moments during each frame that are drawn and averaged into it (1 for no motion blur)
+    pub motion_blur: usize,
+    /// The number of threads drawing frames
+    pub threads: usize,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            num_of_frames: 60 * 60,
+            fps: 60,
+            background_color: [255, 255, 255],
+            mode: RenderMode::default(),
+            motion_blur: 1,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+}
+
+/// Renders `settings.num_of_frames` frames of the simulation and writes them to `output`, calling `on_frame` after each written frame.
+///
+/// The work is split between threads: one advances the simulation and sends snapshots of the balls and walls
+/// to `settings.threads` threads that draw them, while the calling thread writes the drawn frames to `output` in order.
+/// Only a few frames are in flight at any time, so memory use doesn't depend on the length of the video.
+pub fn render_simulation(simulation: &mut Simulation, settings: &RenderSettings, output: &mut dyn FrameSink, mut on_frame: impl FnMut()) -> Result<(), Error> {
+    let (width, height) = (simulation.width() as usize, simulation.height() as usize);
+    let samples = settings.motion_blur.max(1);
+    // with motion blur the simulation is sampled evenly during the frame, the last sample is at the time of the frame
+    let interval = 1.0 / (settings.fps as f64 * samples as f64);
+    let threads = settings.threads.max(1);
+    // the obstacles only turn, so the drawing threads share them and turn them to the time of each snapshot
+    let obstacles = simulation.obstacles().to_vec();
+    let obstacles = obstacles.as_slice();
+    let arena = simulation.physics().arena;
+    // enough frames to keep every thread busy while the others wait to send theirs
+    let capacity = 2 * threads;
+
+    let (snapshot_sender, snapshot_receiver) = mpsc::sync_channel::<(usize, Vec<Snapshot>)>(capacity);
+    let (frame_sender, frame_receiver) = mpsc::sync_channel::<(usize, ImageRGB8)>(capacity);
+    // written frames are sent back, so their memory can be reused for new ones
+    let (spare_sender, spare_receiver) = mpsc::channel::<ImageRGB8>();
+    let snapshot_receiver = Arc::new(Mutex::new(snapshot_receiver));
+    let spare_receiver = Arc::new(Mutex::new(spare_receiver));
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for frame in 0..settings.num_of_frames {
+                let mut states = Vec::with_capacity(samples);
+                for _ in 0..samples {
+                    simulation.step(interval);
+                    states.push((simulation.balls().to_vec(), simulation.walls(), simulation.time()));
+                }
+                // fails only if the drawing threads have stopped because of an error
+                if snapshot_sender.send((frame, states)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..threads {
+            let snapshot_receiver = Arc::clone(&snapshot_receiver);
+            let spare_receiver = Arc::clone(&spare_receiver);
+            let frame_sender = frame_sender.clone();
+            scope.spawn(move || {
+                // buffers for motion blur
+                let mut sample_image = None;
+                let mut sum = vec![];
+                while let Some((frame, states)) = receive(&snapshot_receiver) {
+                    let mut image = receive_spare(&spare_receiver).unwrap_or_else(|| ImageRGB8::new(width, height, settings.background_color));
+                    if let [(balls, walls, time)] = states.as_slice() {
+                        generate_frame(balls, &turned(obstacles, *time), arena, *walls, settings.mode, &mut image);
+                    } else {
+                        let sample_image = sample_image.get_or_insert_with(|| ImageRGB8::new(width, height, settings.background_color));
+                        generate_blurred_frame(&states, obstacles, arena, settings.mode, &mut image, sample_image, &mut sum);
+                    }
+                    if frame_sender.send((frame, image)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // only the drawing threads may hold these, so the channels close as soon as all of them are done
+        drop(snapshot_receiver);
+        drop(frame_sender);
+
+        // frames can finish out of order, so they wait here until all frames before them are written
+        let mut pending = BTreeMap::new();
+        let mut next_frame = 0;
+        for (frame, image) in frame_receiver {
+            pending.insert(frame, image);
+            while let Some(image) = pending.remove(&next_frame) {
+                // returning the error drops the receiver, which stops the other threads
+                output.write_frame(&image)?;
+                let _ = spare_sender.send(image);
+                next_frame += 1;
+                on_frame();
+            }
+        }
+        Ok(())
+    })?;
+
+    output.finish()
+}
+
+// draws all states of the balls and walls and averages them into `img`, `sample_image` and `sum` are reused between frames
+fn generate_blurred_frame(states: &[Snapshot], obstacles: &[Obstacle], arena: Arena, mode: RenderMode, img: &mut ImageRGB8, sample_image: &mut ImageRGB8, sum: &mut Vec<[u32; 3]>) {
+    sum.clear();
+    sum.resize(img.image_data.len(), [0; 3]);
+    for (balls, walls, time) in states {
+        generate_frame(balls, &turned(obstacles, *time), arena, *walls, mode, sample_image);
+        for (sum, pixel) in sum.iter_mut().zip(&sample_image.image_data) {
+            for channel in 0..3 {
+                sum[channel] += pixel[channel] as u32;
+            }
+        }
+    }
+
+    let samples = states.len() as u32;
+    for (pixel, sum) in img.image_data.iter_mut().zip(sum.iter()) {
+        *pixel = sum.map(|channel| ((channel + samples / 2) / samples) as u8);
+    }
+}
+
+// the obstacles where they are at `time`
+fn turned(obstacles: &[Obstacle], time: f64) -> Vec<Obstacle> {
+    obstacles.iter().map(|obstacle| obstacle.at(time)).collect()
+}
+
+// takes the next snapshot from the shared receiver, None if there are no more
+fn receive<T>(receiver: &Mutex<Receiver<T>>) -> Option<T> {
+    receiver.lock().ok()?.recv().ok()
+}
+
+// takes a spare image if there is one
+fn receive_spare(receiver: &Mutex<Receiver<ImageRGB8>>) -> Option<ImageRGB8> {
+    receiver.lock().ok()?.try_recv().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::setup::BallSettings;
+
+    // keeps the frames in memory, and fails after `limit` of them
+    struct MemorySink {
+        frames: Vec<Vec<u8>>,
+        limit: usize,
+        finished: bool,
+    }
+
+    impl FrameSink for MemorySink {
+        fn write_frame(&mut self, img: &ImageRGB8) -> Result<(), Error> {
+            if self.frames.len() == self.limit {
+                return Err(Error::Encoder(String::from("full")));
+            }
+            self.frames.push(img.to_bytes().to_vec());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), Error> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    fn render(settings: &RenderSettings, limit: usize) -> (Result<(), Error>, MemorySink, usize) {
+        let ball_settings = BallSettings { num_of_balls: 12, radius_min: 4.0, radius_max: 12.0, speed_min: 50.0, speed_max: 200.0, ..BallSettings::default() };
+        let balls = ball_settings.generate(160.0, 120.0, &mut ChaCha8Rng::seed_from_u64(3)).unwrap();
+        let mut simulation = Simulation::new(160.0, 120.0, balls);
+        let mut sink = MemorySink { frames: vec![], limit, finished: false };
+        let mut written = 0;
+        let result = render_simulation(&mut simulation, settings, &mut sink, || written += 1);
+        (result, sink, written)
+    }
+
+    #[test]
+    fn renders_the_same_frames_with_any_number_of_threads() {
+        for motion_blur in [1, 3] {
+            let settings = RenderSettings { num_of_frames: 40, motion_blur, threads: 1, ..RenderSettings::default() };
+            let (result, single, written) = render(&settings, usize::MAX);
+            result.unwrap();
+            assert!(single.finished);
+            assert_eq!((single.frames.len(), written), (40, 40));
+            // the balls move, so frames out of order would differ
+            assert!(single.frames.windows(2).all(|frames| frames[0] != frames[1]));
+            for threads in [2, 5] {
+                let (result, multi, _) = render(&RenderSettings { threads, ..settings.clone() }, usize::MAX);
+                result.unwrap();
+                assert!(multi.frames == single.frames, "{threads} threads with motion blur {motion_blur}");
+            }
+        }
+    }
+
+    #[test]
+    fn stops_when_the_output_fails() {
+        let settings = RenderSettings { num_of_frames: 100, threads: 4, ..RenderSettings::default() };
+        let (result, sink, written) = render(&settings, 10);
+        assert!(matches!(result, Err(Error::Encoder(_))));
+        assert!(!sink.finished);
+        assert_eq!((sink.frames.len(), written), (10, 10));
+    }
+}
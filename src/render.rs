@@ -0,0 +1,314 @@
+use tinydraw::ImageRGB8;
+
+use crate::arena::Arena;
+use crate::ball::Ball;
+use crate::obstacle::Obstacle;
+
+
+/// The way the balls are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Whole pixels, with the position and radius rounded to the nearest pixel
+    #[default]
+    Aliased,
+    /// Anti-aliased edges at the exact position and radius, so slow balls move smoothly
+    Smooth,
+}
+
+// the color of the area outside of a round arena or behind moving walls
+const WALL_COLOR: [u8; 3] = [128, 128, 128];
+
+/// Clears `img` and draws the area outside of the `arena` and behind the `walls` (left, right, bottom, top, as returned by [Simulation::walls]),
+/// all `obstacles` and then all `balls` onto it.
+/// In a periodic arena, the parts of them that stick out over an edge are drawn on the other side.
+///
+/// [Simulation::walls]: crate::Simulation::walls
+pub fn generate_frame(balls: &[Ball], obstacles: &[Obstacle], arena: Arena, walls: [f64; 4], mode: RenderMode, img: &mut ImageRGB8) {
+    img.clear();
+    draw_arena(img, arena, mode);
+    draw_walls(img, walls, mode);
+    let (width, height) = (img.width as f64, img.height as f64);
+    let shifts = arena.shifts(width, height);
+    for obstacle in obstacles {
+        for &shift in &shifts {
+            draw_obstacle(img, obstacle, shift, mode);
+        }
+    }
+    for ball in balls {
+        // tinydraw leaves out circles that stick out of the image, as those in a periodic arena can
+        let (x, y, r) = (ball.x.round(), ball.y.round(), ball.r.round());
+        let inside = x >= r && y >= r && x + r < width && y + r < height;
+        if mode == RenderMode::Aliased && inside && ball.filled && ball.outline_width == 0.0 && ball.opacity >= 1.0 {
+            img.draw_circle(x as usize, y as usize, r as usize, ball.color, 0, 1.0);
+        } else {
+            draw_ball(img, ball, mode);
+        }
+        if let Some(color) = ball.spoke_color {
+            draw_spoke(img, ball, color, mode);
+        }
+        // the copies across the edges (the first shift is none), which can be partly outside of the image, so they are always drawn pixel by pixel
+        for &shift in &shifts[1..] {
+            let copy = ball.shifted(shift);
+            if copy.x + copy.r + 1.0 < 0.0 || copy.y + copy.r + 1.0 < 0.0 || copy.x - copy.r - 1.0 > width || copy.y - copy.r - 1.0 > height {
+                continue;
+            }
+            draw_ball(img, &copy, mode);
+            if let Some(color) = copy.spoke_color {
+                draw_spoke(img, &copy, color, mode);
+            }
+        }
+    }
+}
+
+// draws the ball's fill and outline, blending each pixel by how much of it is covered and by the ball's opacity
+fn draw_ball(img: &mut ImageRGB8, ball: &Ball, mode: RenderMode) {
+    // pixel centers are at integer coordinates, and y goes from the bottom of the image up (like in tinydraw)
+    // in smooth mode a pixel is approximated as covered by r + 0.5 - d, where d is the distance of its center from the center of the circle,
+    // in aliased mode like in tinydraw's circles, the pixels whose centers are inside are covered,
+    // and the next pixel out along the row or column (whichever crosses the edge more steeply) is covered by the part of it the edge reaches into
+    let (x, y, r, outline_width, outer_edge, inner_edge) = match mode {
+        RenderMode::Aliased => (ball.x.round(), ball.y.round(), ball.r.round(), ball.outline_width.round(), 1.0, 0.0),
+        RenderMode::Smooth => (ball.x, ball.y, ball.r, ball.outline_width, 0.5, 0.5),
+    };
+    // tinydraw doesn't draw circles with a radius of 0 either
+    if r == 0.0 {
+        return;
+    }
+    let inner_r = (r - outline_width).max(0.0); // the radius of the fill
+    let coverage = |radius: f64, dx: f64, dy: f64| match mode {
+        RenderMode::Aliased => {
+            let (across, along) = if dx.abs() <= dy.abs() { (dx, dy) } else { (dy, dx) };
+            if across.abs() > radius { 0.0 } else { ((radius * radius - across * across).sqrt() + 1.0 - along.abs()).clamp(0.0, 1.0) }
+        },
+        RenderMode::Smooth => (radius + 0.5 - dx.hypot(dy)).clamp(0.0, 1.0),
+    };
+
+    let (width, height) = (img.width as isize, img.height as isize);
+    let (outer_r, solid_r) = (r + outer_edge, inner_r - inner_edge);
+    let y_min = ((y - outer_r).ceil() as isize).max(0);
+    let y_max = ((y + outer_r).floor() as isize).min(height - 1);
+
+    for pixel_y in y_min..=y_max {
+        let dy = pixel_y as f64 - y;
+        let outer_half_width = (outer_r * outer_r - dy * dy).sqrt();
+        if outer_half_width.is_nan() {
+            continue;
+        }
+        let x_min = ((x - outer_half_width).ceil() as isize).max(0);
+        let x_max = ((x + outer_half_width).floor() as isize).min(width - 1);
+        if x_min > x_max {
+            continue;
+        }
+        // the pixels that are completely inside the fill (an empty range if there are none)
+        let (solid_min, solid_max) = if solid_r > dy.abs() {
+            let solid_half_width = (solid_r * solid_r - dy * dy).sqrt();
+            (((x - solid_half_width).ceil() as isize).clamp(x_min, x_max + 1), ((x + solid_half_width).floor() as isize).min(x_max))
+        } else {
+            (x_max + 1, x_max)
+        };
+
+        let row = row(img, pixel_y);
+        for pixel_x in (x_min..solid_min).chain((solid_max + 1).max(x_min)..=x_max) {
+            let dx = pixel_x as f64 - x;
+            let fill = if ball.filled { coverage(inner_r, dx, dy) } else { 0.0 };
+            let outline = if outline_width > 0.0 { coverage(r, dx, dy) - coverage(inner_r, dx, dy) } else { 0.0 };
+            if fill + outline > 0.0 {
+                // a pixel on the inner edge of the outline is partly outline and partly fill
+                let color = mix(ball.outline_color, ball.color, fill / (fill + outline));
+                blend(&mut row[pixel_x as usize], color, (fill + outline) * ball.opacity);
+            }
+        }
+        if ball.filled && solid_min <= solid_max {
+            let solid = &mut row[solid_min as usize..=solid_max as usize];
+            if ball.opacity >= 1.0 {
+                solid.fill(ball.color);
+            } else {
+                solid.iter_mut().for_each(|pixel| blend(pixel, ball.color, ball.opacity));
+            }
+        }
+    }
+}
+
+// fills the area outside of a round arena, in smooth mode a pixel is approximated as covered by 0.5 - d,
+// where d is the distance of its center from the edge (positive inside), estimated from the equation of the ellipse
+fn draw_arena(img: &mut ImageRGB8, arena: Arena, mode: RenderMode) {
+    let (width, height) = (img.width as isize, img.height as isize);
+    let Some(ellipse) = arena.ellipse(width as f64, height as f64) else {
+        return;
+    };
+    let ([center_x, center_y], [a, b]) = (ellipse.center, ellipse.semi_axes);
+    for pixel_y in 0..height {
+        let row = row(img, pixel_y);
+        let dy = pixel_y as f64 - center_y;
+        // the pixels that are surely inside (an empty range if there are none)
+        let half_width = a * (1.0 - (dy.abs() + 1.0).powi(2) / (b * b)).max(0.0).sqrt() - 1.0;
+        let (inside_min, inside_max) = ((center_x - half_width).ceil() as isize, (center_x + half_width).floor() as isize);
+        for pixel_x in (0..inside_min.max(0)).chain((inside_max + 1).max(0)..width) {
+            let dx = pixel_x as f64 - center_x;
+            let value = 1.0 - (dx / a).powi(2) - (dy / b).powi(2);
+            let distance = value / (2.0 * (dx / (a * a)).hypot(dy / (b * b)));
+            let alpha = match mode {
+                RenderMode::Aliased => if distance < 0.0 { 1.0 } else { 0.0 },
+                RenderMode::Smooth => (0.5 - distance).clamp(0.0, 1.0),
+            };
+            paint(&mut row[pixel_x as usize], WALL_COLOR, alpha);
+        }
+    }
+}
+
+// fills the area behind walls that moved into the box, in smooth mode a pixel is approximated as covered by 0.5 - d,
+// where d is the distance of its center from the nearest wall (positive inside),
+// which is half a pixel outside of where the edges of balls can go, like the edges of the image
+fn draw_walls(img: &mut ImageRGB8, walls: [f64; 4], mode: RenderMode) {
+    let (width, height) = (img.width as isize, img.height as isize);
+    let [left, right, bottom, top] = walls;
+    let inside_rows = bottom.ceil() as isize..=top.floor() as isize;
+    for pixel_y in 0..height {
+        let row = row(img, pixel_y);
+        // the pixels that are surely inside (an empty range if there are none)
+        let (inside_min, inside_max) = if inside_rows.contains(&pixel_y) { (left.ceil() as isize, right.floor() as isize) } else { (0, -1) };
+        for pixel_x in (0..inside_min.clamp(0, width)).chain((inside_max + 1).clamp(0, width)..width) {
+            let (x, y) = (pixel_x as f64, pixel_y as f64);
+            let distance = (x - left).min(right - x).min(y - bottom).min(top - y) + 0.5;
+            let alpha = match mode {
+                RenderMode::Aliased => if distance < 0.0 { 1.0 } else { 0.0 },
+                RenderMode::Smooth => (0.5 - distance).clamp(0.0, 1.0),
+            };
+            paint(&mut row[pixel_x as usize], WALL_COLOR, alpha);
+        }
+    }
+}
+
+// draws an obstacle moved by `shift`, in smooth mode a pixel is approximated as covered by 0.5 - d,
+// where d is the signed distance of its center from the edge
+fn draw_obstacle(img: &mut ImageRGB8, obstacle: &Obstacle, shift: [f64; 2], mode: RenderMode) {
+    let coverage = |distance: f64| match mode {
+        RenderMode::Aliased => if distance <= 0.0 { 1.0 } else { 0.0 },
+        RenderMode::Smooth => (0.5 - distance).clamp(0.0, 1.0),
+    };
+
+    let (width, height) = (img.width as isize, img.height as isize);
+    let [x_min, y_min, x_max, y_max] = obstacle.bounds();
+    let [x_min, y_min, x_max, y_max] = [x_min + shift[0], y_min + shift[1], x_max + shift[0], y_max + shift[1]];
+    let x_min = ((x_min - 0.5).ceil() as isize).max(0);
+    let x_max = ((x_max + 0.5).floor() as isize).min(width - 1);
+    let y_min = ((y_min - 0.5).ceil() as isize).max(0);
+    let y_max = ((y_max + 0.5).floor() as isize).min(height - 1);
+    for pixel_y in y_min..=y_max {
+        let row = row(img, pixel_y);
+        for pixel_x in x_min..=x_max {
+            let alpha = coverage(obstacle.signed_distance(pixel_x as f64 - shift[0], pixel_y as f64 - shift[1]));
+            paint(&mut row[pixel_x as usize], obstacle.color, alpha);
+        }
+    }
+}
+
+// draws a line from the center of the ball to its edge in the direction of its angle, so its rotation can be seen
+fn draw_spoke(img: &mut ImageRGB8, ball: &Ball, color: [u8; 3], mode: RenderMode) {
+    let (x, y, r) = match mode {
+        RenderMode::Aliased => (ball.x.round(), ball.y.round(), ball.r.round()),
+        RenderMode::Smooth => (ball.x, ball.y, ball.r),
+    };
+    let half_width = (r / 16.0).max(0.5);
+    let (dx, dy) = (ball.angle.cos(), ball.angle.sin());
+    let coverage = |distance: f64| match mode {
+        RenderMode::Aliased => if distance <= half_width { 1.0 } else { 0.0 },
+        RenderMode::Smooth => (half_width + 0.5 - distance).clamp(0.0, 1.0),
+    };
+
+    let (width, height) = (img.width as isize, img.height as isize);
+    let (end_x, end_y) = (x + dx * r, y + dy * r);
+    let margin = half_width + 1.0;
+    let x_min = ((x.min(end_x) - margin).ceil() as isize).max(0);
+    let x_max = ((x.max(end_x) + margin).floor() as isize).min(width - 1);
+    let y_min = ((y.min(end_y) - margin).ceil() as isize).max(0);
+    let y_max = ((y.max(end_y) + margin).floor() as isize).min(height - 1);
+    for pixel_y in y_min..=y_max {
+        let row = row(img, pixel_y);
+        for pixel_x in x_min..=x_max {
+            let (px, py) = (pixel_x as f64 - x, pixel_y as f64 - y);
+            // the distance from the spoke, which is cut off at the edge of the ball
+            let along = (px * dx + py * dy).clamp(0.0, r);
+            let distance = (px - along * dx).hypot(py - along * dy);
+            let inside = match mode {
+                RenderMode::Aliased => if px.hypot(py) <= r { 1.0 } else { 0.0 },
+                RenderMode::Smooth => (r + 0.5 - px.hypot(py)).clamp(0.0, 1.0),
+            };
+            let alpha = coverage(distance).min(inside);
+            if alpha > 0.0 {
+                blend(&mut row[pixel_x as usize], color, alpha * ball.opacity);
+            }
+        }
+    }
+}
+
+// the pixels of the row `pixel_y` (counted from the bottom of the image, like in tinydraw)
+fn row(img: &mut ImageRGB8, pixel_y: isize) -> &mut [[u8; 3]] {
+    let (width, height) = (img.width as isize, img.height as isize);
+    &mut img.image_data[((height - 1 - pixel_y) * width) as usize..((height - pixel_y) * width) as usize]
+}
+
+// covers the pixel with `color`, or mixes it in if `alpha` is less than 1
+fn paint(pixel: &mut [u8; 3], color: [u8; 3], alpha: f64) {
+    if alpha >= 1.0 {
+        *pixel = color;
+    } else if alpha > 0.0 {
+        blend(pixel, color, alpha);
+    }
+}
+
+// mixes `color` into the pixel, `alpha` is the share of the new color (0 to 1)
+fn blend(pixel: &mut [u8; 3], color: [u8; 3], alpha: f64) {
+    *pixel = mix(*pixel, color, alpha);
+}
+
+// returns the color that is `share` (0 to 1) of `color2` and the rest of `color1`
+fn mix(color1: [u8; 3], color2: [u8; 3], share: f64) -> [u8; 3] {
+    let mut color = [0; 3];
+    for channel in 0..3 {
+        color[channel] = (color1[channel] as f64 * (1.0 - share) + color2[channel] as f64 * share).round() as u8;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    fn pixel(img: &ImageRGB8, x: usize, y: usize) -> u8 {
+        img.get_pixel(x, y).unwrap()[0]
+    }
+
+    #[test]
+    fn covers_pixels_by_how_far_the_edge_reaches_into_them() {
+        let mut img = ImageRGB8::new(30, 30, WHITE);
+        draw_ball(&mut img, &Ball::new(10.3, 10.0, 1.0, 5.0, 0.0, 0.0, [0; 3]), RenderMode::Smooth);
+        // the edge is 0.8 and 0.2 pixels past the centers of the pixels on the right and left end of the middle row
+        assert_eq!(pixel(&img, 15, 10), 51);
+        assert_eq!(pixel(&img, 16, 10), 255);
+        assert_eq!(pixel(&img, 5, 10), 204);
+        assert_eq!(pixel(&img, 4, 10), 255);
+        assert_eq!(pixel(&img, 10, 10), 0);
+        // at half the opacity
+        let mut img = ImageRGB8::new(30, 30, WHITE);
+        draw_ball(&mut img, &Ball { opacity: 0.5, ..Ball::new(10.3, 10.0, 1.0, 5.0, 0.0, 0.0, [0; 3]) }, RenderMode::Smooth);
+        assert_eq!(pixel(&img, 15, 10), 153);
+        assert_eq!(pixel(&img, 10, 10), 128);
+    }
+
+    #[test]
+    fn draws_aliased_balls_like_tinydraw() {
+        for (x, y, r) in [(20.0, 20.0, 10.0), (20.4, 19.6, 7.3), (15.5, 24.0, 1.0), (25.0, 25.0, 14.0), (30.0, 30.0, 0.4)] {
+            let mut expected = ImageRGB8::new(50, 50, WHITE);
+            expected.draw_circle(f64::round(x) as usize, f64::round(y) as usize, f64::round(r) as usize, [200, 0, 0], 0, 1.0);
+            let mut img = ImageRGB8::new(50, 50, WHITE);
+            draw_ball(&mut img, &Ball::new(x, y, 1.0, r, 0.0, 0.0, [200, 0, 0]), RenderMode::Aliased);
+            assert!(img.image_data == expected.image_data, "{x}, {y}, {r}");
+            // the way frames are generated
+            generate_frame(&[Ball::new(x, y, 1.0, r, 0.0, 0.0, [200, 0, 0])], &[], Arena::Rectangle, [0.0, 49.0, 0.0, 49.0], RenderMode::Aliased, &mut img);
+            assert!(img.image_data == expected.image_data, "{x}, {y}, {r}");
+        }
+    }
+}
@@ -0,0 +1,676 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::arena::Arena;
+use crate::ball::{Ball, MassModel};
+use crate::error::Error;
+use crate::obstacle::{Obstacle, Rotation, Shape};
+use crate::setup::validate_style;
+use crate::simulation::{Fragmentation, Interactions, MovingWalls, Physics, Simulation};
+use crate::{hex_to_rgb, rgb_to_hex};
+
+
+// the largest share of the box between moving walls where they stop that the balls can cover,
+// pushed closer together they jam (randomly packed discs cover about 82%, and less along walls),
+// and long before that they collide so often and get so fast that the simulation practically stops
+const MAX_PACKING: f64 = 0.7;
+
+/// A complete description of the initial state of a simulation.
+/// Can be loaded from and saved to TOML or JSON files (chosen by the file extension).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    /// The width of the box
+    pub width: f64,
+    /// The height of the box
+    pub height: f64,
+    /// The background color
+    pub background_color: [u8; 3],
+    /// The balls in their initial state
+    pub balls: Vec<Ball>,
+    /// The static obstacles inside the box
+    pub obstacles: Vec<Obstacle>,
+    /// The forces acting on the balls
+    pub physics: Physics,
+}
+
+impl Scene {
+    /// Reads the scene from a TOML file, or a JSON file if the extension is `.json`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).map_err(|err| Error::io(format!("Can't read scene file {}", path.display()), err))?;
+        let file: SceneFile = if is_json(path) {
+            serde_json::from_str(&contents).map_err(|err| Error::Validation(format!("Invalid scene file {}: {}", path.display(), err)))?
+        } else {
+            toml::from_str(&contents).map_err(|err| Error::Validation(format!("Invalid scene file {}: {}", path.display(), err)))?
+        };
+        let scene = file.into_scene()?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Writes the scene to a TOML file, or a JSON file if the extension is `.json`.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = SceneFile::from_scene(self);
+        let contents = if is_json(path) {
+            serde_json::to_string_pretty(&file).map_err(|err| Error::Validation(format!("Can't save scene: {}", err)))?
+        } else {
+            toml::to_string(&file).map_err(|err| Error::Validation(format!("Can't save scene: {}", err)))?
+        };
+        fs::write(path, contents).map_err(|err| Error::io(format!("Can't write scene file {}", path.display()), err))
+    }
+
+    /// Checks that the box is not empty, that all numbers are finite, that all balls are inside it without overlapping each other or the obstacles
+    /// (also across the edges of a periodic arena), that the obstacles and physics are valid
+    /// that moving walls stop far enough from each other and from the obstacles for any ball to fit in between,
+    /// and far enough from each other for the balls not to be packed too densely (they may cover at most 70% of the area between them),
+    /// and that rotating obstacles turn far enough from the walls and the other obstacles for any ball to fit in between.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(self.width > 0.0 && self.width.is_finite() && self.height > 0.0 && self.height.is_finite()) {
+            return Err(Error::Validation(String::from("Width and height must be finite and greater than 0")));
+        }
+        self.physics.validate()?;
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            obstacle.validate().map_err(|err| Error::Validation(format!("Obstacle {}: {}", i, err)))?;
+        }
+        for (i, ball) in self.balls.iter().enumerate() {
+            if ![ball.x, ball.y, ball.v_x, ball.v_y, ball.angle, ball.omega].iter().all(|value| value.is_finite()) {
+                return Err(Error::Validation(format!("Ball {}: Position, velocity, angle and angular velocity must be finite", i)));
+            }
+            if ![ball.r, ball.m, ball.inertia].iter().all(|value| *value > 0.0 && value.is_finite()) {
+                return Err(Error::Validation(format!("Ball {} must have finite positive radius, mass and moment of inertia", i)));
+            }
+            validate_style(ball.outline_width, ball.filled, ball.opacity).map_err(|err| Error::Validation(format!("Ball {}: {}", i, err)))?;
+            if ball.restitution.is_some_and(|restitution| !(0.0..=1.0).contains(&restitution)) {
+                return Err(Error::Validation(format!("Ball {}: Restitution must be between 0 and 1", i)));
+            }
+            if !ball.charge.is_finite() {
+                return Err(Error::Validation(format!("Ball {}: Charge must be finite", i)));
+            }
+            if !self.physics.contains(ball, self.width, self.height, 0.0) {
+                return Err(Error::Validation(format!("Ball {} is outside of the area", i)));
+            }
+            let arena = self.physics.arena;
+            for (j, other) in self.balls[..i].iter().enumerate() {
+                if arena.balls_overlap(ball, other, self.width, self.height) {
+                    return Err(Error::Validation(format!("Balls {} and {} overlap", j, i)));
+                }
+            }
+            if let Some(j) = self.obstacles.iter().position(|obstacle| arena.overlaps_obstacle(ball, obstacle, self.width, self.height)) {
+                return Err(Error::Validation(format!("Ball {} overlaps obstacle {}", i, j)));
+            }
+        }
+        // otherwise balls would be crushed
+        let diameter = self.balls.iter().map(|ball| 2.0 * ball.r).fold(0.0, f64::max);
+        if let Some(moving_walls) = &self.physics.moving_walls {
+            // the walls start far enough apart for the balls inside them, and move in straight lines, so it is enough to check where they stop
+            let [left, right, bottom, top] = self.physics.walls(self.width, self.height, moving_walls.duration);
+            if right - left < diameter || top - bottom < diameter {
+                return Err(Error::Validation(String::from("The walls must stop far enough apart for every ball to fit between them")));
+            }
+            let area: f64 = self.balls.iter().map(|ball| PI * ball.r * ball.r).sum();
+            if area > MAX_PACKING * (right - left) * (top - bottom) {
+                return Err(Error::Validation(format!("The walls must stop far enough apart for the balls to cover at most {}% of the area between them", MAX_PACKING * 100.0)));
+            }
+            let moving = moving_walls.speeds.map(|speed| speed > 0.0 && moving_walls.duration > 0.0);
+            for (i, obstacle) in self.obstacles.iter().enumerate() {
+                let [x_min, y_min, x_max, y_max] = obstacle.bounds();
+                if (moving[0] && x_min < left + diameter) || (moving[1] && x_max > right - diameter)
+                    || (moving[2] && y_min < bottom + diameter) || (moving[3] && y_max > top - diameter) {
+                    return Err(Error::Validation(format!("The walls must stop far enough from obstacle {} for every ball to fit in between", i)));
+                }
+            }
+        }
+        // the walls closest to the middle of the box, at the start or where they stop
+        let [start, end] = [0.0, f64::INFINITY].map(|time| self.physics.walls(self.width, self.height, time));
+        let [left, right, bottom, top] = [start[0].max(end[0]), start[1].min(end[1]), start[2].max(end[2]), start[3].min(end[3])];
+        let arena = self.physics.arena;
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            let Some((pivot, reach)) = obstacle.swept_disc() else {
+                continue;
+            };
+            let clearance = reach + diameter;
+            let inside = match arena {
+                Arena::Rectangle => pivot[0] - clearance >= left && pivot[0] + clearance <= right && pivot[1] - clearance >= bottom && pivot[1] + clearance <= top,
+                _ => arena.contains(pivot[0], pivot[1], clearance, self.width, self.height),
+            };
+            if !inside {
+                return Err(Error::Validation(format!("Obstacle {} must turn far enough from the walls for every ball to fit in between", i)));
+            }
+            for (j, other) in self.obstacles.iter().enumerate().filter(|&(j, _)| j != i) {
+                let too_close = match other.swept_disc() {
+                    Some((other_pivot, other_reach)) => {
+                        let shift = arena.shift_towards(other_pivot, pivot, self.width, self.height);
+                        (pivot[0] - other_pivot[0] - shift[0]).hypot(pivot[1] - other_pivot[1] - shift[1]) < clearance + other_reach
+                    },
+                    None => arena.shifts(self.width, self.height).into_iter().any(|[x, y]| other.signed_distance(pivot[0] + x, pivot[1] + y) < clearance),
+                };
+                if too_close {
+                    return Err(Error::Validation(format!("Obstacle {} must turn far enough from obstacle {} for every ball to fit in between", i, j)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a simulation starting from this scene.
+    pub fn simulation(&self) -> Simulation {
+        Simulation::with_obstacles(self.width, self.height, self.balls.clone(), self.obstacles.clone(), self.physics.clone())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+// the on-disk representation of a scene
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    width: f64,
+    height: f64,
+    #[serde(default = "default_background_color")]
+    background_color: String,
+    #[serde(default, skip_serializing_if = "is_rectangle")]
+    arena: Arena,
+    // used for balls without an explicit mass
+    #[serde(default = "default_ball_mass")]
+    ball_mass: MassModel,
+    #[serde(default, skip_serializing_if = "is_zero_vector")]
+    gravity: [f64; 2],
+    #[serde(default = "default_restitution", skip_serializing_if = "is_one")]
+    restitution: f64,
+    #[serde(default = "default_restitution", skip_serializing_if = "is_one")]
+    wall_restitution: f64,
+    #[serde(default = "default_contact_time", skip_serializing_if = "is_default_contact_time")]
+    contact_time: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    linear_drag: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    quadratic_drag: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    friction: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    wall_friction: f64,
+    // balls don't merge if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merging: Option<MassModel>,
+    // balls don't break if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fragmentation: Option<SceneFragmentation>,
+    // the walls don't move if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    moving_walls: Option<SceneMovingWalls>,
+    // balls don't interact at a distance if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interactions: Option<SceneInteractions>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    obstacles: Vec<SceneObstacle>,
+    #[serde(default)]
+    balls: Vec<SceneBall>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneBall {
+    x: f64,
+    y: f64,
+    r: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mass: Option<f64>,
+    #[serde(default)]
+    v_x: f64,
+    #[serde(default)]
+    v_y: f64,
+    // the moment of inertia, derived from the mass with `ball_mass` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inertia: Option<f64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    angle: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    omega: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    charge: f64,
+    #[serde(default = "default_ball_color")]
+    color: String,
+    // the same as `color` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    outline_color: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    outline_width: f64,
+    #[serde(default = "default_filled", skip_serializing_if = "is_true")]
+    filled: bool,
+    #[serde(default = "default_opacity", skip_serializing_if = "is_one")]
+    opacity: f64,
+    // the global one if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    restitution: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spoke_color: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFragmentation {
+    speed: f64,
+    #[serde(default = "default_min_fragment_radius")]
+    min_radius: f64,
+    #[serde(default = "default_fragments")]
+    fragments: usize,
+    #[serde(default = "default_ball_mass")]
+    model: MassModel,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneMovingWalls {
+    // left, right, bottom, top
+    #[serde(default, skip_serializing_if = "is_zero_walls")]
+    offsets: [f64; 4],
+    speeds: [f64; 4],
+    duration: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneInteractions {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    gravitational_constant: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    coulomb_constant: f64,
+    #[serde(default = "default_time_step")]
+    time_step: f64,
+    #[serde(default = "default_theta")]
+    theta: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "lowercase", deny_unknown_fields)]
+enum SceneObstacle {
+    Segment {
+        start: [f64; 2],
+        end: [f64; 2],
+        #[serde(default = "default_segment_width")]
+        width: f64,
+        #[serde(default = "default_obstacle_color")]
+        color: String,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angular_velocity: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pivot: Option<[f64; 2]>,
+    },
+    Polygon {
+        points: Vec<[f64; 2]>,
+        #[serde(default = "default_obstacle_color")]
+        color: String,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angular_velocity: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pivot: Option<[f64; 2]>,
+    },
+    Circle {
+        center: [f64; 2],
+        radius: f64,
+        #[serde(default = "default_obstacle_color")]
+        color: String,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        angular_velocity: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pivot: Option<[f64; 2]>,
+    },
+}
+
+fn default_background_color() -> String {
+    String::from("#ffffff")
+}
+
+fn default_ball_color() -> String {
+    String::from("#000000")
+}
+
+fn default_obstacle_color() -> String {
+    String::from("#808080")
+}
+
+fn default_segment_width() -> f64 {
+    2.0
+}
+
+fn default_ball_mass() -> MassModel {
+    MassModel::Circle
+}
+
+fn default_min_fragment_radius() -> f64 {
+    Fragmentation::default().min_radius
+}
+
+fn default_fragments() -> usize {
+    Fragmentation::default().fragments
+}
+
+fn default_time_step() -> f64 {
+    Interactions::default().time_step
+}
+
+fn default_theta() -> f64 {
+    Interactions::default().theta
+}
+
+fn default_filled() -> bool {
+    true
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+fn default_restitution() -> f64 {
+    1.0
+}
+
+fn default_contact_time() -> f64 {
+    Physics::default().contact_time
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+fn is_one(value: &f64) -> bool {
+    *value == 1.0
+}
+
+fn is_default_contact_time(value: &f64) -> bool {
+    *value == default_contact_time()
+}
+
+fn is_zero_vector(value: &[f64; 2]) -> bool {
+    *value == [0.0, 0.0]
+}
+
+fn is_zero_walls(value: &[f64; 4]) -> bool {
+    *value == [0.0; 4]
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn is_rectangle(arena: &Arena) -> bool {
+    *arena == Arena::Rectangle
+}
+
+impl SceneFile {
+    fn from_scene(scene: &Scene) -> Self {
+        Self {
+            width: scene.width,
+            height: scene.height,
+            background_color: rgb_to_hex(scene.background_color),
+            arena: scene.physics.arena,
+            ball_mass: default_ball_mass(),
+            gravity: scene.physics.gravity,
+            restitution: scene.physics.restitution,
+            wall_restitution: scene.physics.wall_restitution,
+            contact_time: scene.physics.contact_time,
+            linear_drag: scene.physics.linear_drag,
+            quadratic_drag: scene.physics.quadratic_drag,
+            friction: scene.physics.friction,
+            wall_friction: scene.physics.wall_friction,
+            merging: scene.physics.merging,
+            fragmentation: scene.physics.fragmentation.map(|fragmentation| SceneFragmentation {
+                speed: fragmentation.speed,
+                min_radius: fragmentation.min_radius,
+                fragments: fragmentation.fragments,
+                model: fragmentation.model,
+            }),
+            moving_walls: scene.physics.moving_walls.map(|moving_walls| SceneMovingWalls {
+                offsets: moving_walls.offsets,
+                speeds: moving_walls.speeds,
+                duration: moving_walls.duration,
+            }),
+            interactions: scene.physics.interactions.map(|interactions| SceneInteractions {
+                gravitational_constant: interactions.gravitational_constant,
+                coulomb_constant: interactions.coulomb_constant,
+                time_step: interactions.time_step,
+                theta: interactions.theta,
+            }),
+            obstacles: scene.obstacles.iter().map(|obstacle| {
+                let color = rgb_to_hex(obstacle.color);
+                let angular_velocity = obstacle.rotation.map_or(0.0, |rotation| rotation.angular_velocity);
+                let pivot = obstacle.rotation.map(|rotation| rotation.pivot);
+                match &obstacle.shape {
+                    Shape::Segment { start, end, width } => SceneObstacle::Segment { start: *start, end: *end, width: *width, color, angular_velocity, pivot },
+                    Shape::Polygon { points } => SceneObstacle::Polygon { points: points.clone(), color, angular_velocity, pivot },
+                    Shape::Circle { center, radius } => SceneObstacle::Circle { center: *center, radius: *radius, color, angular_velocity, pivot },
+                }
+            }).collect(),
+            balls: scene.balls.iter().map(|ball| SceneBall {
+                x: ball.x,
+                y: ball.y,
+                r: ball.r,
+                mass: Some(ball.m),
+                v_x: ball.v_x,
+                v_y: ball.v_y,
+                inertia: Some(ball.inertia),
+                angle: ball.angle,
+                omega: ball.omega,
+                charge: ball.charge,
+                color: rgb_to_hex(ball.color),
+                outline_color: (ball.outline_color != ball.color).then(|| rgb_to_hex(ball.outline_color)),
+                outline_width: ball.outline_width,
+                filled: ball.filled,
+                opacity: ball.opacity,
+                restitution: ball.restitution,
+                spoke_color: ball.spoke_color.map(rgb_to_hex),
+            }).collect(),
+        }
+    }
+
+    fn into_scene(self) -> Result<Scene, Error> {
+        let mut balls = Vec::with_capacity(self.balls.len());
+        for (i, ball) in self.balls.into_iter().enumerate() {
+            let color = hex_to_rgb(&ball.color).map_err(|err| Error::Validation(format!("Ball {}: {}", i, err)))?;
+            let outline_color = match &ball.outline_color {
+                Some(outline_color) => hex_to_rgb(outline_color).map_err(|err| Error::Validation(format!("Ball {}: {}", i, err)))?,
+                None => color,
+            };
+            let spoke_color = match &ball.spoke_color {
+                Some(spoke_color) => Some(hex_to_rgb(spoke_color).map_err(|err| Error::Validation(format!("Ball {}: {}", i, err)))?),
+                None => None,
+            };
+            let mass = ball.mass.unwrap_or_else(|| self.ball_mass.mass(ball.r));
+            let mut scene_ball = Ball::new(ball.x, ball.y, mass, ball.r, ball.v_x, ball.v_y, color);
+            scene_ball.inertia = ball.inertia.unwrap_or_else(|| self.ball_mass.inertia(mass, ball.r));
+            scene_ball.angle = ball.angle;
+            scene_ball.omega = ball.omega;
+            scene_ball.charge = ball.charge;
+            scene_ball.outline_color = outline_color;
+            scene_ball.outline_width = ball.outline_width;
+            scene_ball.filled = ball.filled;
+            scene_ball.opacity = ball.opacity;
+            scene_ball.restitution = ball.restitution;
+            scene_ball.spoke_color = spoke_color;
+            balls.push(scene_ball);
+        }
+        let mut obstacles = Vec::with_capacity(self.obstacles.len());
+        for (i, obstacle) in self.obstacles.into_iter().enumerate() {
+            let (shape, color, angular_velocity, pivot) = match obstacle {
+                SceneObstacle::Segment { start, end, width, color, angular_velocity, pivot } => (Shape::Segment { start, end, width }, color, angular_velocity, pivot),
+                SceneObstacle::Polygon { points, color, angular_velocity, pivot } => (Shape::Polygon { points }, color, angular_velocity, pivot),
+                SceneObstacle::Circle { center, radius, color, angular_velocity, pivot } => (Shape::Circle { center, radius }, color, angular_velocity, pivot),
+            };
+            let color = hex_to_rgb(&color).map_err(|err| Error::Validation(format!("Obstacle {}: {}", i, err)))?;
+            let mut obstacle = Obstacle { shape, color, rotation: None };
+            if angular_velocity != 0.0 {
+                // by default it turns around the center of the box around it
+                let pivot = pivot.unwrap_or_else(|| {
+                    let [x_min, y_min, x_max, y_max] = obstacle.bounds();
+                    [(x_min + x_max) / 2.0, (y_min + y_max) / 2.0]
+                });
+                obstacle.rotation = Some(Rotation { pivot, angular_velocity });
+            }
+            obstacles.push(obstacle);
+        }
+        Ok(Scene {
+            width: self.width,
+            height: self.height,
+            background_color: hex_to_rgb(&self.background_color).map_err(|err| Error::Validation(format!("Background: {}", err)))?,
+            balls,
+            obstacles,
+            physics: Physics {
+                gravity: self.gravity,
+                restitution: self.restitution,
+                wall_restitution: self.wall_restitution,
+                contact_time: self.contact_time,
+                linear_drag: self.linear_drag,
+                quadratic_drag: self.quadratic_drag,
+                friction: self.friction,
+                wall_friction: self.wall_friction,
+                merging: self.merging,
+                fragmentation: self.fragmentation.map(|fragmentation| Fragmentation {
+                    speed: fragmentation.speed,
+                    min_radius: fragmentation.min_radius,
+                    fragments: fragmentation.fragments,
+                    model: fragmentation.model,
+                }),
+                arena: self.arena,
+                moving_walls: self.moving_walls.map(|moving_walls| MovingWalls {
+                    offsets: moving_walls.offsets,
+                    speeds: moving_walls.speeds,
+                    duration: moving_walls.duration,
+                }),
+                interactions: self.interactions.map(|interactions| Interactions {
+                    gravitational_constant: interactions.gravitational_constant,
+                    coulomb_constant: interactions.coulomb_constant,
+                    time_step: interactions.time_step,
+                    theta: interactions.theta,
+                }),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a scene that uses everything a scene file can describe
+    fn scene() -> Scene {
+        let mut fancy = Ball::new(700.0, 400.0, 3.0, 15.0, -20.0, 35.5, [10, 20, 30]);
+        fancy.inertia = 500.0;
+        fancy.angle = 1.25;
+        fancy.omega = -2.0;
+        fancy.charge = -0.75;
+        fancy.outline_color = [200, 100, 0];
+        fancy.outline_width = 3.0;
+        fancy.filled = false;
+        fancy.opacity = 0.5;
+        fancy.restitution = Some(0.8);
+        fancy.spoke_color = Some([255, 0, 0]);
+        Scene {
+            width: 800.0,
+            height: 600.0,
+            background_color: [1, 2, 3],
+            balls: vec![Ball::new(100.0, 100.0, 1.0, 10.0, 50.0, 0.0, [0; 3]), fancy, Ball::new(500.0, 450.0, 0.1, 5.0, 0.0, 0.0, [0; 3])],
+            obstacles: vec![
+                Obstacle { shape: Shape::Segment { start: [250.0, 250.0], end: [350.0, 250.0], width: 10.0 }, color: [9, 9, 9], rotation: Some(Rotation { pivot: [300.0, 250.0], angular_velocity: 1.5 }) },
+                Obstacle { shape: Shape::Polygon { points: vec![[600.0, 100.0], [650.0, 100.0], [650.0, 150.0]] }, color: [0; 3], rotation: None },
+            ],
+            physics: Physics {
+                gravity: [0.0, -100.0],
+                restitution: 0.9,
+                wall_restitution: 0.7,
+                contact_time: 0.02,
+                linear_drag: 0.1,
+                quadratic_drag: 0.001,
+                friction: 0.3,
+                wall_friction: 0.2,
+                merging: Some(MassModel::Ball),
+                fragmentation: Some(Fragmentation { speed: 400.0, ..Fragmentation::default() }),
+                arena: Arena::Rectangle,
+                moving_walls: Some(MovingWalls { offsets: [10.0, 0.0, 0.0, 0.0], speeds: [0.0, 0.0, 0.0, 50.0], duration: 2.0 }),
+                interactions: Some(Interactions { gravitational_constant: 10.0, coulomb_constant: 5.0, ..Interactions::default() }),
+            },
+        }
+    }
+
+    // the scene in a TOML file
+    fn parse(contents: &str) -> Result<Scene, Error> {
+        let file: SceneFile = toml::from_str(contents).map_err(|err| Error::Validation(err.to_string()))?;
+        let scene = file.into_scene()?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    fn message<T: std::fmt::Debug>(result: Result<T, Error>) -> String {
+        match result {
+            Err(Error::Validation(message)) => message,
+            other => panic!("{other:?} isn't a validation error"),
+        }
+    }
+
+    #[test]
+    fn loads_saved_scenes() {
+        let scene = scene();
+        scene.validate().unwrap();
+        for extension in ["toml", "json"] {
+            let path = std::env::temp_dir().join(format!("circle-bounce-scene-{}.{extension}", std::process::id()));
+            scene.save(&path).unwrap();
+            let loaded = Scene::load(&path);
+            fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.unwrap(), scene);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let scene = "width = 100.0\nheight = 100.0\n";
+        assert!(parse(scene).is_ok());
+        assert!(message(parse(&format!("{scene}gravityy = [0.0, 1.0]\n"))).contains("unknown field"));
+        assert!(message(parse(&format!("{scene}[[balls]]\nx = 50.0\ny = 50.0\nr = 5.0\nradius = 5.0\n"))).contains("unknown field"));
+        assert!(message(parse(&format!("{scene}[[obstacles]]\nshape = \"circle\"\ncenter = [50.0, 50.0]\nradius = 5.0\nwidth = 2.0\n"))).contains("unknown field"));
+        assert!(message(parse(&format!("{scene}[moving_walls]\nspeeds = [0.0, 0.0, 0.0, 1.0]\nduration = 1.0\nspeed = 1.0\n"))).contains("unknown field"));
+    }
+
+    #[test]
+    fn rejects_balls_packed_too_densely_by_the_walls() {
+        // the walls stop 99 pixels apart, where the balls may cover at most 70% of 99×99
+        let physics = Physics { moving_walls: Some(MovingWalls { offsets: [0.0; 4], speeds: [0.0, 50.0, 0.0, 50.0], duration: 2.0 }), ..Physics::default() };
+        let balls = [[30.0, 30.0], [90.0, 30.0], [30.0, 90.0], [90.0, 90.0]].map(|[x, y]| Ball::new(x, y, 1.0, 25.0, 0.0, 0.0, [0; 3]));
+        let scene = Scene { width: 200.0, height: 200.0, background_color: [255; 3], balls: balls[..3].to_vec(), obstacles: vec![], physics };
+        scene.validate().unwrap();
+        let crowded = Scene { balls: balls.to_vec(), ..scene.clone() };
+        assert!(message(crowded.validate()).contains("at most 70%"));
+        let narrow = Scene { balls: vec![Ball::new(100.0, 100.0, 1.0, 60.0, 0.0, 0.0, [0; 3])], ..scene };
+        assert!(message(narrow.validate()).contains("for every ball to fit between them"));
+    }
+
+    #[test]
+    fn rejects_obstacles_turning_too_close_to_others() {
+        // it reaches 55 pixels from its pivot, and balls with a diameter of 20 have to fit past it
+        let bar = Obstacle { shape: Shape::Segment { start: [50.0, 100.0], end: [150.0, 100.0], width: 10.0 }, color: [0; 3], rotation: Some(Rotation { pivot: [100.0, 100.0], angular_velocity: 1.0 }) };
+        let scene = Scene {
+            width: 400.0,
+            height: 200.0,
+            background_color: [255; 3],
+            balls: vec![Ball::new(300.0, 170.0, 1.0, 10.0, 0.0, 0.0, [0; 3])],
+            obstacles: vec![bar.clone(), Obstacle { shape: Shape::Circle { center: [190.0, 100.0], radius: 5.0 }, color: [0; 3], rotation: None }],
+            physics: Physics::default(),
+        };
+        scene.validate().unwrap();
+
+        let near_wall = Scene { width: 160.0, obstacles: vec![bar.clone()], balls: vec![Ball::new(20.0, 170.0, 1.0, 10.0, 0.0, 0.0, [0; 3])], ..scene.clone() };
+        assert_eq!(message(near_wall.validate()), "Obstacle 0 must turn far enough from the walls for every ball to fit in between");
+        let mut near_obstacle = scene.clone();
+        near_obstacle.obstacles[1].shape = Shape::Circle { center: [170.0, 100.0], radius: 5.0 };
+        assert_eq!(message(near_obstacle.validate()), "Obstacle 0 must turn far enough from obstacle 1 for every ball to fit in between");
+        // the discs two turning ones sweep have to stay the balls' diameter apart
+        let mut turning = scene;
+        turning.obstacles[1] = Obstacle { shape: Shape::Circle { center: [210.0, 100.0], radius: 10.0 }, color: [0; 3], rotation: Some(Rotation { pivot: [200.0, 100.0], angular_velocity: -1.0 }) };
+        turning.validate().unwrap();
+        turning.obstacles[1].rotation = Some(Rotation { pivot: [190.0, 100.0], angular_velocity: -1.0 });
+        turning.obstacles[1].shape = Shape::Circle { center: [200.0, 100.0], radius: 10.0 };
+        assert_eq!(message(turning.validate()), "Obstacle 0 must turn far enough from obstacle 1 for every ball to fit in between");
+    }
+}
@@ -0,0 +1,201 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::arena::Arena;
+use crate::ball::{Ball, MassModel};
+use crate::error::Error;
+
+
+/// Settings used to generate a random set of balls.
+#[derive(Clone, Debug)]
+pub struct BallSettings {
+    /// The number of balls to generate
+    pub num_of_balls: usize,
+    /// The minimum radius of the balls (the radii are whole numbers)
+    pub radius_min: f64,
+    /// The maximum radius of the balls
+    pub radius_max: f64,
+    /// The minimum speed of the balls (per axis)
+    pub speed_min: f64,
+    /// The maximum speed of the balls (per axis)
+    pub speed_max: f64,
+    /// The way of calculating the mass of the balls
+    pub mass: MassModel,
+    /// The color of the balls
+    pub color: [u8; 3],
+    /// Use random color for the balls (never equal to `background_color`)
+    pub color_random: bool,
+    /// The background color the balls are drawn on
+    pub background_color: [u8; 3],
+    /// The color of the outlines (the same as the fill color of each ball if not set)
+    pub outline_color: Option<[u8; 3]>,
+    /// The width of the outlines (0 for no outlines)
+    pub outline_width: f64,
+    /// Fill the balls (otherwise only the outlines are drawn)
+    pub filled: bool,
+    /// The opacity of the balls (0 to 1)
+    pub opacity: f64,
+    /// The color of the spokes that show the rotation of the balls (no spokes if not set)
+    pub spoke_color: Option<[u8; 3]>,
+    /// The magnitude of the charge of the balls, each one gets it with a random sign
+    pub charge: f64,
+}
+
+impl Default for BallSettings {
+    fn default() -> Self {
+        Self {
+            num_of_balls: 25,
+            radius_min: 50.0,
+            radius_max: 100.0,
+            speed_min: 80.0,
+            speed_max: 130.0,
+            mass: MassModel::Circle,
+            color: [0, 0, 0],
+            color_random: false,
+            background_color: [255, 255, 255],
+            outline_color: None,
+            outline_width: 0.0,
+            filled: true,
+            opacity: 1.0,
+            spoke_color: None,
+            charge: 0.0,
+        }
+    }
+}
+
+impl BallSettings {
+    /// Generates non-overlapping balls inside a `width`×`height` area.
+    /// Returns [Error::Validation] if the settings are invalid or [Error::Placement] if the balls can't be placed.
+    pub fn generate<R: Rng>(&self, width: f64, height: f64, rng: &mut R) -> Result<Vec<Ball>, Error> {
+        self.generate_in(Arena::Rectangle, width, height, rng)
+    }
+
+    /// Generates non-overlapping balls inside the `arena` of a `width`×`height` area, like [BallSettings::generate].
+    pub fn generate_in<R: Rng>(&self, arena: Arena, width: f64, height: f64, rng: &mut R) -> Result<Vec<Ball>, Error> {
+        if !(width > 0.0 && width.is_finite()) {
+            return Err(Error::Validation(String::from("Width must be finite and greater than 0")));
+        }
+        if !(height > 0.0 && height.is_finite()) {
+            return Err(Error::Validation(String::from("Height must be finite and greater than 0")));
+        }
+        if self.num_of_balls == 0 {
+            return Err(Error::Validation(String::from("Number of balls must be greater than 0")));
+        }
+        if !(self.radius_min.is_finite() && self.radius_max.is_finite()) {
+            return Err(Error::Validation(String::from("Radius must be finite")));
+        }
+        if self.radius_min <= 0.0 {
+            return Err(Error::Validation(String::from("Minimum radius must be greater than 0")));
+        }
+        if self.radius_max < self.radius_min {
+            return Err(Error::Validation(String::from("Maximum radius must be greater than or equal to minimum radius")));
+        }
+        if self.radius_max.floor() < self.radius_min.ceil() {
+            return Err(Error::Validation(String::from("There must be a whole number between minimum and maximum radius")));
+        }
+        if !(self.speed_min.is_finite() && self.speed_max.is_finite()) {
+            return Err(Error::Validation(String::from("Speed must be finite")));
+        }
+        if self.speed_min < 0.0 {
+            return Err(Error::Validation(String::from("Minimum speed must not be negative")));
+        }
+        if self.speed_max < self.speed_min {
+            return Err(Error::Validation(String::from("Maximum speed must be greater than or equal to minimum speed")));
+        } else if self.speed_max <= 0.0 {
+            return Err(Error::Validation(String::from("Maximum speed must be greater than 0")));
+        }
+        validate_style(self.outline_width, self.filled, self.opacity)?;
+        if !self.charge.is_finite() {
+            return Err(Error::Validation(String::from("Charge must be finite")));
+        }
+
+        let mut balls: Vec<Ball> = Vec::with_capacity(self.num_of_balls);
+        for _ in 0..self.num_of_balls {
+            // radius
+            let radius = rng.gen_range((self.radius_min.ceil() as u128)..=(self.radius_max.floor() as u128)) as f64;
+            if radius >= (width - radius - 1.0) || radius >= (height - radius - 1.0) {
+                return Err(Error::Placement(String::from("Can't fit all balls in the given area")));
+            }
+
+            // mass
+            let mass = self.mass.mass(radius);
+            let mut ball = Ball::new(0.0, 0.0, mass, radius, 0.0, 0.0, self.color);
+
+            // x, y (with the same overlap test as for fragments placed during the simulation)
+            let mut tries = 0;
+            loop {
+                tries += 1;
+                if tries > 100_000 {
+                    return Err(Error::Placement(String::from("Can't fit all balls in the given area")));
+                }
+                ball.x = rng.gen_range(radius..(width - radius - 1.0));
+                ball.y = rng.gen_range(radius..(height - radius - 1.0));
+                if arena.contains(ball.x, ball.y, radius, width, height) && !balls.iter().any(|other| arena.balls_overlap(&ball, other, width, height)) {
+                    break;
+                }
+            }
+
+            // speed (vx, vy)
+            let speed_x: f64 = (*[-1, 1].choose(rng).unwrap() as f64) * rng.gen_range(self.speed_min..=self.speed_max);
+            let speed_y: f64 = (*[-1, 1].choose(rng).unwrap() as f64) * rng.gen_range(self.speed_min..=self.speed_max);
+
+            // color
+            let color: [u8; 3] = if self.color_random {
+                let mut color_temp: [u8; 3] = rng.gen();
+                while color_temp == self.background_color {
+                    color_temp = rng.gen();
+                }
+                color_temp
+            } else {
+                self.color
+            };
+
+            ball.v_x = speed_x;
+            ball.v_y = speed_y;
+            ball.color = color;
+            ball.inertia = self.mass.inertia(mass, radius);
+            ball.outline_color = self.outline_color.unwrap_or(color);
+            ball.outline_width = self.outline_width;
+            ball.filled = self.filled;
+            ball.opacity = self.opacity;
+            ball.spoke_color = self.spoke_color;
+            if self.charge != 0.0 {
+                ball.charge = *[-1.0, 1.0].choose(rng).unwrap() * self.charge;
+            }
+            balls.push(ball);
+        }
+
+        Ok(balls)
+    }
+}
+
+// checks the settings that affect only how a ball looks
+pub(crate) fn validate_style(outline_width: f64, filled: bool, opacity: f64) -> Result<(), Error> {
+    if !(outline_width >= 0.0 && outline_width.is_finite()) {
+        return Err(Error::Validation(String::from("Outline width must be finite and not negative")));
+    }
+    if !filled && outline_width == 0.0 {
+        return Err(Error::Validation(String::from("Balls without fill must have an outline")));
+    }
+    if !(0.0..=1.0).contains(&opacity) {
+        return Err(Error::Validation(String::from("Opacity must be between 0 and 1")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn generates_balls_with_whole_radii() {
+        let settings = BallSettings { num_of_balls: 50, radius_min: 4.5, radius_max: 12.7, ..BallSettings::default() };
+        let balls = settings.generate(800.0, 600.0, &mut ChaCha8Rng::seed_from_u64(1)).unwrap();
+        assert!(balls.iter().all(|ball| ball.r.fract() == 0.0 && (5.0..=12.0).contains(&ball.r)));
+        let settings = BallSettings { radius_min: 4.2, radius_max: 4.8, ..settings };
+        assert!(matches!(settings.generate(800.0, 600.0, &mut ChaCha8Rng::seed_from_u64(1)), Err(Error::Validation(_))));
+    }
+}
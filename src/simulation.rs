@@ -0,0 +1,1556 @@
+use std::f64::consts::{PI, TAU};
+
+use tinydraw::ImageRGB8;
+
+use crate::arena::Arena;
+use crate::ball::{Ball, MassModel};
+use crate::error::Error;
+use crate::events::{EventKind, EventQueue};
+use crate::forces;
+use crate::grid::Grid;
+use crate::motion::Axis;
+use crate::obstacle::{Capsule, Obstacle, Rotation};
+use crate::render::{RenderMode, generate_frame};
+
+/// Forces acting on all balls in a simulation and the way they collide.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Physics {
+    /// The acceleration of all balls (e.g. `[0.0, -500.0]` pulls them down)
+    pub gravity: [f64; 2],
+    /// The coefficient of restitution of collisions between balls (1 is elastic, 0 is perfectly inelastic),
+    /// for two balls with different coefficients ([Ball::restitution]) the average is used
+    pub restitution: f64,
+    /// The coefficient of restitution of collisions with walls and obstacles
+    pub wall_restitution: f64,
+    /// Collisions of a ball that happen sooner than this after its previous one (in seconds) are elastic,
+    /// and balls pressed together by gravity bounce apart for at least this long,
+    /// so that balls coming to rest keep bouncing invisibly instead of colliding infinitely often in a finite time
+    /// (only with gravity or moving walls, otherwise every collision loses energy)
+    pub contact_time: f64,
+    /// The deceleration of balls proportional to their velocity (per second)
+    pub linear_drag: f64,
+    /// The deceleration of balls proportional to their velocity squared (per pixel),
+    /// applied separately along each axis, so that the balls' paths can still be calculated exactly
+    pub quadratic_drag: f64,
+    /// The coefficient of friction between balls, which makes them spin
+    /// (the tangential impulse of a collision is at most this many times the normal one)
+    pub friction: f64,
+    /// The coefficient of friction between balls and walls or obstacles
+    pub wall_friction: f64,
+    /// If set, colliding balls merge into one instead of bouncing, keeping their mass, momentum and angular momentum,
+    /// its area (for [MassModel::Circle]) or volume (for [MassModel::Ball]) is the sum of theirs.
+    /// It starts at their center of mass, unless it would stick out of the arena, then it is moved just inside
+    /// (balls that can't merge that way bounce instead)
+    pub merging: Option<MassModel>,
+    /// If set, balls that collide fast enough break into fragments (unless they merge)
+    pub fragmentation: Option<Fragmentation>,
+    /// The shape of the box the balls bounce around in
+    pub arena: Arena,
+    /// If set, the walls of a rectangular arena move into or out of it, like pistons
+    pub moving_walls: Option<MovingWalls>,
+    /// If set, balls attract each other by gravity and attract or repel each other by their charges ([Ball::charge])
+    pub interactions: Option<Interactions>,
+}
+
+impl Physics {
+    /// Checks that gravity is finite, the coefficients of restitution are between 0 and 1, the contact time, drag and friction aren't negative,
+    /// that balls break into at least two fragments of positive size, that only the walls of a rectangular arena move, without leaving the box,
+    /// and that the forces between balls are finite and calculated in positive time steps, outside of a periodic arena.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.gravity.iter().all(|g| g.is_finite()) {
+            return Err(Error::Validation(String::from("Gravity must be finite")));
+        }
+        if !(0.0..=1.0).contains(&self.restitution) || !(0.0..=1.0).contains(&self.wall_restitution) {
+            return Err(Error::Validation(String::from("Restitution must be between 0 and 1")));
+        }
+        if !(self.contact_time >= 0.0 && self.contact_time.is_finite()) {
+            return Err(Error::Validation(String::from("Contact time must be finite and not negative")));
+        }
+        if !(self.linear_drag >= 0.0 && self.linear_drag.is_finite() && self.quadratic_drag >= 0.0 && self.quadratic_drag.is_finite()) {
+            return Err(Error::Validation(String::from("Drag must be finite and not negative")));
+        }
+        if !(self.friction >= 0.0 && self.friction.is_finite() && self.wall_friction >= 0.0 && self.wall_friction.is_finite()) {
+            return Err(Error::Validation(String::from("Friction must be finite and not negative")));
+        }
+        if let Some(fragmentation) = &self.fragmentation {
+            if !(fragmentation.speed >= 0.0 && fragmentation.speed.is_finite()) {
+                return Err(Error::Validation(String::from("Fragmentation speed must be finite and not negative")));
+            }
+            if !(fragmentation.min_radius > 0.0 && fragmentation.min_radius.is_finite()) {
+                return Err(Error::Validation(String::from("Minimum fragment radius must be finite and greater than 0")));
+            }
+            if fragmentation.fragments < 2 {
+                return Err(Error::Validation(String::from("Balls must break into at least 2 fragments")));
+            }
+        }
+        if let Some(moving_walls) = &self.moving_walls {
+            if self.arena != Arena::Rectangle {
+                return Err(Error::Validation(String::from("Only the walls of a rectangular arena can move")));
+            }
+            if !moving_walls.offsets.iter().all(|offset| *offset >= 0.0 && offset.is_finite()) {
+                return Err(Error::Validation(String::from("Wall offsets must be finite and not negative")));
+            }
+            if !moving_walls.speeds.iter().all(|speed| speed.is_finite()) {
+                return Err(Error::Validation(String::from("Wall speeds must be finite")));
+            }
+            if !(moving_walls.duration >= 0.0 && moving_walls.duration.is_finite()) {
+                return Err(Error::Validation(String::from("The time the walls move must be finite and not negative")));
+            }
+            if moving_walls.travel(moving_walls.duration).iter().any(|travel| *travel < 0.0) {
+                return Err(Error::Validation(String::from("The walls can't move out of the box")));
+            }
+        }
+        if let Some(interactions) = &self.interactions {
+            if self.arena == Arena::Periodic {
+                return Err(Error::Validation(String::from("Forces between balls can't be used in a periodic arena")));
+            }
+            if !(interactions.gravitational_constant.is_finite() && interactions.coulomb_constant.is_finite()) {
+                return Err(Error::Validation(String::from("The gravitational and Coulomb constants must be finite")));
+            }
+            if !(interactions.time_step > 0.0 && interactions.time_step.is_finite()) {
+                return Err(Error::Validation(String::from("Time step must be finite and greater than 0")));
+            }
+            if !(interactions.theta >= 0.0 && interactions.theta.is_finite()) {
+                return Err(Error::Validation(String::from("Barnes-Hut opening angle must be finite and not negative")));
+            }
+        }
+        Ok(())
+    }
+
+    // the motion along the x and y axes
+    pub(crate) fn axes(&self) -> [Axis; 2] {
+        self.gravity.map(|acceleration| Axis { acceleration, linear_drag: self.linear_drag, quadratic_drag: self.quadratic_drag })
+    }
+
+    // the positions of the walls of a rectangular arena at `time` (left, right, bottom, top), as far as the edges of balls can go
+    pub(crate) fn walls(&self, width: f64, height: f64, time: f64) -> [f64; 4] {
+        let [left, right, bottom, top] = self.moving_walls.map_or([0.0; 4], |moving_walls| moving_walls.travel(time));
+        [left, width - 1.0 - right, bottom, height - 1.0 - top]
+    }
+
+    // whether a ball is inside the arena at `time`, which the walls of a rectangular one may have moved into
+    pub(crate) fn contains(&self, ball: &Ball, width: f64, height: f64, time: f64) -> bool {
+        if self.arena != Arena::Rectangle {
+            return self.arena.contains(ball.x, ball.y, ball.r, width, height);
+        }
+        let [left, right, bottom, top] = self.walls(width, height, time);
+        ball.x >= left + ball.r && ball.y >= bottom + ball.r && ball.x <= right - ball.r && ball.y <= top - ball.r
+    }
+
+    // the contact time at `time`, 0 if neither gravity nor moving walls press balls together
+    fn contact_time_at(&self, time: f64) -> f64 {
+        let walls_moving = self.moving_walls.is_some_and(|moving_walls| time < moving_walls.duration && moving_walls.speeds.iter().any(|speed| *speed != 0.0));
+        if self.gravity != [0.0, 0.0] || walls_moving { self.contact_time } else { 0.0 }
+    }
+
+    // the velocity of a wall along its axis at `time`, and how much longer it keeps moving
+    fn wall_motion(&self, wall: usize, time: f64) -> (f64, f64) {
+        match self.moving_walls {
+            Some(moving_walls) if time < moving_walls.duration => {
+                let speed = moving_walls.speeds[wall];
+                (if wall.is_multiple_of(2) { speed } else { -speed }, moving_walls.duration - time)
+            },
+            _ => (0.0, 0.0),
+        }
+    }
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self {
+            gravity: [0.0, 0.0],
+            restitution: 1.0,
+            wall_restitution: 1.0,
+            contact_time: 0.01,
+            linear_drag: 0.0,
+            quadratic_drag: 0.0,
+            friction: 0.0,
+            wall_friction: 0.0,
+            merging: None,
+            fragmentation: None,
+            arena: Arena::Rectangle,
+            moving_walls: None,
+            interactions: None,
+        }
+    }
+}
+
+/// The way balls break apart in violent collisions.
+///
+/// A ball breaks into equal fragments, evenly spaced around its center so that neighbouring ones touch,
+/// which share its mass, momentum and angular momentum. The balls bounce apart as if they collided at `speed`,
+/// and the rest of the energy they would bounce apart with makes the fragments fly apart.
+/// A ball stays whole if its fragments would be smaller than `min_radius` or there is no room for them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fragmentation {
+    /// The speed towards each other above which colliding balls break
+    pub speed: f64,
+    /// The radius of the smallest possible fragment
+    pub min_radius: f64,
+    /// The number of fragments a ball breaks into
+    pub fragments: usize,
+    /// The area (for [MassModel::Circle]) or volume (for [MassModel::Ball]) of the fragments adds up to that of the ball
+    pub model: MassModel,
+}
+
+impl Default for Fragmentation {
+    fn default() -> Self {
+        Self {
+            speed: 500.0,
+            min_radius: 5.0,
+            fragments: 4,
+            model: MassModel::Circle,
+        }
+    }
+}
+
+/// Walls of a rectangular arena that start at some distance from the edges of the box, move with constant speeds, and then stop.
+///
+/// Balls that hit a moving wall bounce off it as off a wall at rest in a frame moving with it,
+/// so a wall that moves into a gas of balls compresses and heats it, like a piston, and one that moves out cools it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MovingWalls {
+    /// How far each wall (left, right, bottom, top) is from its edge of the box at the start (in pixels)
+    pub offsets: [f64; 4],
+    /// How fast each wall (left, right, bottom, top) moves into the arena (in pixels per second), negative speeds move it out towards the edge
+    pub speeds: [f64; 4],
+    /// How long the walls move (in seconds), after that they stay where they are
+    pub duration: f64,
+}
+
+impl MovingWalls {
+    /// How far each wall (left, right, bottom, top) is from its edge of the box at `time`.
+    pub fn travel(&self, time: f64) -> [f64; 4] {
+        let time = time.clamp(0.0, self.duration);
+        std::array::from_fn(|wall| self.offsets[wall] + self.speeds[wall] * time)
+    }
+}
+
+/// Long-range forces between balls: Newtonian gravity between their masses and Coulomb's law between their charges.
+///
+/// Both fall off with the square of the distance between the centers of the balls.
+/// They are calculated in time steps, between which the balls move and collide exactly as without them,
+/// and for many balls, distant groups of them act as one ball at their center (the Barnes-Hut approximation).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interactions {
+    /// The strength of gravity between balls, the acceleration of a ball towards another one is this times the other's mass over their distance squared
+    /// (negative makes balls repel each other)
+    pub gravitational_constant: f64,
+    /// The strength of the force between charges, balls with charges of the same sign repel each other
+    pub coulomb_constant: f64,
+    /// The longest time (in seconds) between two updates of the velocities by the forces
+    pub time_step: f64,
+    /// How far a group of balls has to be to act as one: the size of the group over its distance must be below this
+    /// (0 calculates the forces between all pairs of balls exactly, 0.5 is accurate to about a percent)
+    pub theta: f64,
+}
+
+impl Default for Interactions {
+    fn default() -> Self {
+        Self {
+            gravitational_constant: 0.0,
+            coulomb_constant: 0.0,
+            time_step: 0.002,
+            theta: 0.5,
+        }
+    }
+}
+
+/// A box of bouncing balls, possibly with obstacles inside, that can be advanced in time and rendered.
+/// The box is a rectangle, whose walls can move ([Physics::moving_walls]), or the circle or ellipse inside it,
+/// or it has no walls and balls leaving it come back on the other side ([Physics::arena]).
+///
+/// The simulation is event driven: the times of all future collisions are predicted and kept in a priority queue,
+/// so advancing it only costs work proportional to the number of collisions that actually happen.
+/// Collisions are only predicted between balls in neighbouring cells of a grid,
+/// so the cost of a collision depends on how crowded the area around it is, not on the total number of balls.
+///
+/// With [Physics::merging], balls are removed during the simulation, the last ball takes the place of a removed one,
+/// and with [Physics::fragmentation], new balls are added after the last one.
+/// With [Physics::interactions], the forces between balls change their paths in regular time steps.
+pub struct Simulation {
+    width: f64,
+    height: f64,
+    physics: Physics,
+    time: f64,
+    balls: Vec<Ball>,
+    obstacles: Vec<Obstacle>,
+    // the parts of the obstacles that balls collide with
+    capsules: Vec<Capsule>,
+    // the time at which each ball's position is valid (balls are moved lazily, only when needed)
+    ball_times: Vec<f64>,
+    // the time of the last collision of each ball (with another ball or a wall)
+    collision_times: Vec<f64>,
+    events: EventQueue,
+    grid: Grid,
+    // reused buffer for neighbours (or parts of obstacles) from the grid
+    neighbours: Vec<usize>,
+}
+
+impl Simulation {
+    /// Creates a new simulation of `balls` inside a `width`×`height` box without any forces.
+    /// The balls shouldn't overlap each other or the walls.
+    pub fn new(width: f64, height: f64, balls: Vec<Ball>) -> Self {
+        Self::with_physics(width, height, balls, Physics::default())
+    }
+
+    /// Creates a new simulation of `balls` inside a `width`×`height` box with the given forces.
+    /// The balls shouldn't overlap each other or the walls.
+    pub fn with_physics(width: f64, height: f64, balls: Vec<Ball>, physics: Physics) -> Self {
+        Self::with_obstacles(width, height, balls, vec![], physics)
+    }
+
+    /// Creates a new simulation of `balls` inside a `width`×`height` box with `obstacles` and the given forces.
+    /// The balls shouldn't overlap each other, the walls or the obstacles.
+    pub fn with_obstacles(width: f64, height: f64, mut balls: Vec<Ball>, obstacles: Vec<Obstacle>, physics: Physics) -> Self {
+        let num_of_balls = balls.len();
+        for ball in &mut balls {
+            physics.arena.wrap(ball, width, height);
+        }
+        // in a periodic arena balls can also hit the copies of obstacles across the edges, the ones they can reach are added
+        // (the biggest ball is at most half as wide as the box)
+        let shifts = physics.arena.shifts(width, height);
+        let capsules: Vec<Capsule> = obstacles.iter().flat_map(Obstacle::capsules)
+            .flat_map(|capsule| shifts.iter().map(move |&[x, y]| Capsule {
+                start: [capsule.start[0] + x, capsule.start[1] + y],
+                end: [capsule.end[0] + x, capsule.end[1] + y],
+                radius: capsule.radius,
+                rotation: capsule.rotation.map(|rotation| Rotation { pivot: [rotation.pivot[0] + x, rotation.pivot[1] + y], ..rotation }),
+            }))
+            .filter(|capsule| {
+                let [x_min, y_min, x_max, y_max] = capsule.bounds();
+                x_max >= -width / 2.0 && y_max >= -height / 2.0 && x_min <= 1.5 * width && y_min <= 1.5 * height
+            })
+            .collect();
+        let mut simulation = Self {
+            width,
+            height,
+            time: 0.0,
+            grid: Grid::new(width, height, &balls, &capsules, physics.arena == Arena::Periodic),
+            physics,
+            balls,
+            obstacles,
+            capsules,
+            ball_times: vec![0.0; num_of_balls],
+            collision_times: vec![f64::NEG_INFINITY; num_of_balls],
+            events: EventQueue::new(num_of_balls),
+            neighbours: vec![],
+        };
+        simulation.predict_all();
+        simulation
+    }
+
+    /// The width of the box
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// The height of the box
+    pub fn height(&self) -> f64 {
+        self.height
+    }
+
+    /// The forces acting on the balls
+    pub fn physics(&self) -> &Physics {
+        &self.physics
+    }
+
+    /// The time that has been simulated so far
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// The current positions of the walls of a rectangular arena (left, right, bottom, top), as far as the edges of balls can go,
+    /// which are the edges of the box, minus a pixel on the right and top, unless the walls move
+    pub fn walls(&self) -> [f64; 4] {
+        self.physics.walls(self.width, self.height, self.time)
+    }
+
+    /// The current state of the balls (merging and fragmentation change their number and order)
+    pub fn balls(&self) -> &[Ball] {
+        &self.balls
+    }
+
+    /// The obstacles inside the box, where they are at the start (rotating ones are turned by [Obstacle::at] at [Simulation::time])
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    // the obstacles where they are now
+    fn current_obstacles(&self) -> Vec<Obstacle> {
+        self.obstacles.iter().map(|obstacle| obstacle.at(self.time)).collect()
+    }
+
+    /// Consumes the simulation and returns its balls.
+    pub fn into_balls(self) -> Vec<Ball> {
+        self.balls
+    }
+
+    /// Clears `img` and draws the current state of the simulation onto it.
+    pub fn render(&self, mode: RenderMode, img: &mut ImageRGB8) {
+        generate_frame(&self.balls, &self.current_obstacles(), self.physics.arena, self.walls(), mode, img);
+    }
+
+    /// Advances the simulation by `interval` seconds, resolving every collision on the way.
+    ///
+    /// With [Physics::interactions], the interval is split into time steps of the leapfrog (drift-kick-drift) integrator:
+    /// the balls move on their usual paths, colliding as always, for half a step, then the forces between them
+    /// change their velocities at once, and they move for another half.
+    pub fn step(&mut self, interval: f64) {
+        let end_time = self.time + interval;
+        let Some(interactions) = self.physics.interactions else {
+            self.advance(end_time);
+            return;
+        };
+        let start_time = self.time;
+        let steps = (interval / interactions.time_step).ceil().max(1.0);
+        let time_step = interval / steps;
+        for step in 0..steps as usize {
+            self.advance(start_time + (step as f64 + 0.5) * time_step);
+            self.kick(&interactions, time_step);
+        }
+        self.advance(end_time);
+    }
+
+    // resolves every collision until `end_time` and moves all balls there
+    fn advance(&mut self, end_time: f64) {
+        while let Some(event) = self.events.pop_until(end_time) {
+            self.time = event.time;
+            match event.kind {
+                EventKind::Balls(ball1, ball2) => {
+                    self.move_ball(ball1, event.time);
+                    self.move_ball(ball2, event.time);
+                    // in a periodic arena they can touch across an edge, then the second one is moved next to the first one until they collided
+                    let position = [self.balls[ball2].x, self.balls[ball2].y];
+                    let shift = self.physics.arena.shift_towards(position, [self.balls[ball1].x, self.balls[ball1].y], self.width, self.height);
+                    self.balls[ball2].x += shift[0];
+                    self.balls[ball2].y += shift[1];
+                    if let Some(model) = self.physics.merging {
+                        if self.merge(ball1, ball2, model) {
+                            continue;
+                        }
+                    }
+                    let contact_time = self.physics.contact_time_at(event.time);
+                    let restitution = if event.time - self.collision_times[ball1].max(self.collision_times[ball2]) < contact_time {
+                        1.0
+                    } else {
+                        (self.balls[ball1].restitution.unwrap_or(self.physics.restitution) + self.balls[ball2].restitution.unwrap_or(self.physics.restitution)) / 2.0
+                    };
+                    let min_speed = self.physics.gravity[0].hypot(self.physics.gravity[1]) * contact_time / 2.0;
+                    // balls that collide fast enough break, if there is room for their fragments
+                    let speed = approach_speed(&self.balls, ball1, ball2);
+                    let fragmentation = self.physics.fragmentation.filter(|fragmentation| speed > fragmentation.speed);
+                    let direction = (self.balls[ball2].y - self.balls[ball1].y).atan2(self.balls[ball2].x - self.balls[ball1].x);
+                    let directions = [direction, direction + PI];
+                    let mut breaking = [false; 2];
+                    if let Some(fragmentation) = &fragmentation {
+                        let fragments = self.placed_fragments(ball1, fragmentation, directions[0], &[]);
+                        breaking[0] = fragments.is_some();
+                        breaking[1] = self.placed_fragments(ball2, fragmentation, directions[1], fragments.as_deref().unwrap_or_default()).is_some();
+                    }
+                    let broken = breaking.contains(&true);
+                    // the energy the balls would bounce apart with, above what a collision at the fragmentation speed would have,
+                    // is shared between the fragments of both balls instead
+                    let (restitution, spread_speed) = match &fragmentation {
+                        Some(fragmentation) if broken => {
+                            let (m1, m2) = (self.balls[ball1].m, self.balls[ball2].m);
+                            let spread_energy = m1 * m2 / (m1 + m2) * (speed * speed - fragmentation.speed * fragmentation.speed) / (m1 + m2);
+                            (restitution * fragmentation.speed / speed, restitution * spread_energy.sqrt())
+                        },
+                        _ => (restitution, 0.0),
+                    };
+                    collide_balls(&mut self.balls, ball1, ball2, restitution, min_speed, self.physics.friction);
+                    [self.balls[ball2].x, self.balls[ball2].y] = position;
+                    self.collision_times[ball1] = event.time;
+                    self.collision_times[ball2] = event.time;
+
+                    self.events.invalidate(ball1);
+                    self.events.invalidate(ball2);
+                    if let Some(fragmentation) = &fragmentation {
+                        for ((ball, direction), breaking) in [ball1, ball2].into_iter().zip(directions).zip(breaking) {
+                            if breaking {
+                                self.fragment(ball, fragmentation, direction, spread_speed);
+                            }
+                        }
+                    }
+                    if self.grid.is_crowded() {
+                        // the fragments are smaller than the balls the grid was made for,
+                        // rebuilding it predicts all events again, so predicting these balls once more would duplicate theirs
+                        self.rebuild();
+                        continue;
+                    }
+                    // fragments of one ball can still hit the other one
+                    self.predict(ball1, (!broken).then_some(ball2));
+                    // quadratic drag curves their paths, so they can meet again after moving apart, and so can balls going across the edges of a periodic arena
+                    let straight_apart = self.physics.quadratic_drag == 0.0 && self.physics.arena != Arena::Periodic;
+                    self.predict(ball2, (!broken && straight_apart).then_some(ball1));
+                },
+                EventKind::Wall(ball, wall) => {
+                    self.move_ball(ball, event.time);
+                    let contact_time = self.physics.contact_time_at(event.time);
+                    let restitution = if event.time - self.collision_times[ball] < contact_time {
+                        1.0
+                    } else {
+                        self.balls[ball].restitution.unwrap_or(self.physics.wall_restitution)
+                    };
+                    if let Some(ellipse) = self.physics.arena.ellipse(self.width, self.height) {
+                        let normal = ellipse.normal(&self.balls[ball]);
+                        let pressure = -(normal[0] * self.physics.gravity[0] + normal[1] * self.physics.gravity[1]);
+                        collide_surface(&mut self.balls[ball], normal, [0.0, 0.0], restitution, pressure * contact_time / 2.0, self.physics.wall_friction);
+                    } else {
+                        // the part of gravity that pushes the ball into the wall
+                        let pressure = match wall {
+                            0 => -self.physics.gravity[0],
+                            1 => self.physics.gravity[0],
+                            2 => -self.physics.gravity[1],
+                            _ => self.physics.gravity[1],
+                        };
+                        let (wall_speed, _) = self.physics.wall_motion(wall, event.time);
+                        collide_wall(&mut self.balls[ball], wall, wall_speed, restitution, pressure * contact_time / 2.0, self.physics.wall_friction);
+                    }
+                    self.collision_times[ball] = event.time;
+
+                    self.events.invalidate(ball);
+                    self.predict(ball, None);
+                },
+                EventKind::Cell(ball, boundary) => {
+                    self.move_ball(ball, event.time);
+                    if self.grid.cross(ball, boundary) {
+                        // it left a periodic arena, so it comes back in on the other side,
+                        // where the copies of obstacles that it was going to hit are somewhere else
+                        let ball_ref = &mut self.balls[ball];
+                        match boundary {
+                            0 => ball_ref.x += self.width,
+                            1 => ball_ref.x -= self.width,
+                            2 => ball_ref.y += self.height,
+                            _ => ball_ref.y -= self.height,
+                        }
+                        self.events.invalidate(ball);
+                        self.predict(ball, None);
+                        continue;
+                    }
+
+                    // the path doesn't change, so events predicted so far stay valid, only new neighbours have to be checked
+
+                    self.predict_cell(ball);
+                    let mut neighbours = std::mem::take(&mut self.neighbours);
+                    self.grid.new_neighbours(ball, boundary, &mut neighbours);
+                    for &other in &neighbours {
+                        self.predict_balls(ball, other);
+                    }
+                    self.grid.new_capsules(ball, boundary, &mut neighbours);
+                    for &capsule in &neighbours {
+                        self.predict_capsule(ball, capsule);
+                    }
+                    self.neighbours = neighbours;
+                },
+                EventKind::Approach(ball1, ball2) => {
+                    self.move_ball(ball1, event.time);
+                    self.move_ball(ball2, event.time);
+                    self.predict_balls(ball1, ball2);
+                },
+                EventKind::WallApproach(ball) => {
+                    self.move_ball(ball, event.time);
+                    self.predict_walls(ball);
+                },
+                EventKind::Obstacle(ball, capsule) => {
+                    self.move_ball(ball, event.time);
+                    let contact_time = self.physics.contact_time_at(event.time);
+                    let restitution = if event.time - self.collision_times[ball] < contact_time {
+                        1.0
+                    } else {
+                        self.balls[ball].restitution.unwrap_or(self.physics.wall_restitution)
+                    };
+                    let capsule = self.capsules[capsule].at(event.time);
+                    let ball_ref = &mut self.balls[ball];
+                    let normal = capsule.normal(ball_ref);
+                    let velocity = capsule.velocity([ball_ref.x - normal[0] * ball_ref.r, ball_ref.y - normal[1] * ball_ref.r]);
+                    // the part of gravity that pushes the ball into the obstacle
+                    let pressure = -(normal[0] * self.physics.gravity[0] + normal[1] * self.physics.gravity[1]);
+                    collide_surface(ball_ref, normal, velocity, restitution, pressure * contact_time / 2.0, self.physics.wall_friction);
+                    self.collision_times[ball] = event.time;
+
+                    self.events.invalidate(ball);
+                    self.predict(ball, None);
+                },
+                EventKind::ObstacleApproach(ball, capsule) => {
+                    self.move_ball(ball, event.time);
+                    self.predict_capsule(ball, capsule);
+                },
+            }
+        }
+
+        for ball in 0..self.balls.len() {
+            self.move_ball(ball, end_time);
+        }
+        self.time = end_time;
+    }
+
+    // changes the velocities of all balls by what the forces between them accelerate them by over `time_step`,
+    // the events of the balls whose paths change are predicted again
+    fn kick(&mut self, interactions: &Interactions, time_step: f64) {
+        // without any forces, the tree doesn't have to be built
+        if interactions.gravitational_constant == 0.0 && (interactions.coulomb_constant == 0.0 || self.balls.iter().all(|ball| ball.charge == 0.0)) {
+            return;
+        }
+        let accelerations = forces::accelerations(&self.balls, interactions);
+        let kicked: Vec<bool> = accelerations.iter().map(|&acceleration| acceleration != [0.0, 0.0]).collect();
+        for (ball, [a_x, a_y]) in accelerations.into_iter().enumerate().filter(|&(ball, _)| kicked[ball]) {
+            self.move_ball(ball, self.time);
+            self.balls[ball].v_x += a_x * time_step;
+            self.balls[ball].v_y += a_y * time_step;
+            self.events.invalidate(ball);
+        }
+        // the balls stay in their cells, so the grid doesn't change
+        for ball in (0..self.balls.len()).filter(|&ball| kicked[ball]) {
+            self.predict_walls(ball);
+            self.predict_obstacles(ball);
+            self.predict_cell(ball);
+            // the events of pairs of kicked balls are predicted once
+            self.predict_neighbours(ball, |other| !kicked[other] || other > ball);
+        }
+    }
+
+    // replaces two colliding balls with the ball they merge into, unless it wouldn't fit into the box or would overlap an obstacle
+    fn merge(&mut self, ball1: usize, ball2: usize, model: MassModel) -> bool {
+        let mut merged = merge_balls(&self.balls[ball1], &self.balls[ball2], model);
+        // the same limit for the rectangle and the circle or ellipse inside it, and it has to fit between the walls now and where they stop
+        // (they move in straight lines, so then it fits between them all the time)
+        let fits_walls = [self.time, f64::INFINITY].into_iter().all(|time| {
+            let [left, right, bottom, top] = self.physics.walls(self.width, self.height, time);
+            2.0 * merged.r <= (right - left).min(top - bottom)
+        });
+        if 2.0 * merged.r + 1.0 > self.width.min(self.height) || !fits_walls {
+            return false;
+        }
+        // it is bigger than both of them, so it can stick out of the box, then it is moved in as far as needed,
+        // which moves the center of mass of all balls a little, but keeps their mass, momentum and angular momentum
+        // (in a periodic arena it only wraps around)
+        match self.physics.arena.ellipse(self.width, self.height) {
+            None if self.physics.arena == Arena::Periodic => self.physics.arena.wrap(&mut merged, self.width, self.height),
+            None => {
+                let [left, right, bottom, top] = self.physics.walls(self.width, self.height, self.time);
+                merged.x = merged.x.min(right - merged.r).max(left + merged.r);
+                merged.y = merged.y.min(top - merged.r).max(bottom + merged.r);
+            },
+            Some(ellipse) => {
+                // pushed in along the normal, which is only approximately enough for an ellipse
+                for _ in 0..100 {
+                    let gap = ellipse.gap(&merged);
+                    if gap >= 0.0 {
+                        break;
+                    }
+                    let normal = ellipse.normal(&merged);
+                    let push = -gap + merged.r * 1e-9;
+                    merged.x += push * normal[0];
+                    merged.y += push * normal[1];
+                }
+                if ellipse.gap(&merged) < 0.0 {
+                    return false;
+                }
+            },
+        }
+        if self.current_obstacles().iter().any(|obstacle| self.physics.arena.overlaps_obstacle(&merged, obstacle, self.width, self.height)) {
+            return false;
+        }
+        let radius = merged.r;
+
+        // the one with the higher index is removed, so that the other one keeps its place
+        let (kept, removed) = (ball1.min(ball2), ball1.max(ball2));
+        self.balls[kept] = merged;
+        self.collision_times[kept] = self.time;
+        self.remove_ball(removed);
+        if self.grid.fits(radius) {
+            self.grid.update(kept, self.balls[kept].x, self.balls[kept].y);
+            self.events.invalidate(kept);
+            self.predict(kept, None);
+            if removed < self.balls.len() {
+                self.predict(removed, None);
+            }
+        } else {
+            self.rebuild();
+        }
+
+        // balls that it grew into merge with it right away
+        let mut neighbours = std::mem::take(&mut self.neighbours);
+        self.grid.neighbours(kept, &mut neighbours);
+        for &other in &neighbours {
+            self.move_ball(other, self.time);
+            if self.physics.arena.balls_overlap(&self.balls[kept], &self.balls[other], self.width, self.height) {
+                self.events.push(self.time, EventKind::Balls(kept, other));
+            }
+        }
+        self.neighbours = neighbours;
+        true
+    }
+
+    // the fragments a ball hit from `direction` would break into, unless they would be too small
+    // or overlap the walls, the obstacles, the balls around it or `others`
+    fn placed_fragments(&mut self, ball: usize, fragmentation: &Fragmentation, direction: f64, others: &[Ball]) -> Option<Vec<Ball>> {
+        let mut fragments = break_ball(&self.balls[ball], fragmentation, direction, 0.0)?;
+        // the balls around it that the fragments could overlap
+        let mut nearby = std::mem::take(&mut self.neighbours);
+        let reach = fragments.iter().map(|fragment| (fragment.x - self.balls[ball].x).hypot(fragment.y - self.balls[ball].y) + fragment.r).fold(0.0, f64::max);
+        self.grid.nearby(self.balls[ball].x, self.balls[ball].y, reach, &mut nearby);
+        nearby.retain(|&other| other != ball);
+        for &other in &nearby {
+            self.move_ball(other, self.time);
+        }
+        let (arena, width, height) = (self.physics.arena, self.width, self.height);
+        for fragment in &mut fragments {
+            arena.wrap(fragment, width, height);
+        }
+        let obstacles = self.current_obstacles();
+        let fits = fragments.iter().all(|fragment| {
+            self.physics.contains(fragment, width, height, self.time)
+                && !nearby.iter().map(|&other| &self.balls[other]).chain(others).any(|other| arena.balls_overlap(fragment, other, width, height))
+                && !obstacles.iter().any(|obstacle| arena.overlaps_obstacle(fragment, obstacle, width, height))
+        });
+        self.neighbours = nearby;
+        fits.then_some(fragments)
+    }
+
+    // replaces a ball with its fragments, the ball itself is replaced with the first one, which still has to be predicted
+    fn fragment(&mut self, ball: usize, fragmentation: &Fragmentation, direction: f64, spread_speed: f64) {
+        let Some(mut fragments) = break_ball(&self.balls[ball], fragmentation, direction, spread_speed) else {
+            return;
+        };
+        for fragment in &mut fragments {
+            self.physics.arena.wrap(fragment, self.width, self.height);
+        }
+        let mut fragments = fragments.into_iter();
+        if let Some(first) = fragments.next() {
+            self.grid.update(ball, first.x, first.y);
+            self.balls[ball] = first;
+        }
+        for fragment in fragments {
+            let fragment = self.add_ball(fragment);
+            self.predict(fragment, None);
+        }
+    }
+
+    // adds a ball at the current time, returns its index
+    fn add_ball(&mut self, ball: Ball) -> usize {
+        self.grid.insert(ball.x, ball.y);
+        self.balls.push(ball);
+        self.ball_times.push(self.time);
+        self.collision_times.push(self.time);
+        self.events.insert();
+        self.balls.len() - 1
+    }
+
+    // removes a ball, the last ball takes its place, so its events have to be predicted again
+    fn remove_ball(&mut self, ball: usize) {
+        self.balls.swap_remove(ball);
+        self.ball_times.swap_remove(ball);
+        self.collision_times.swap_remove(ball);
+        self.grid.remove(ball);
+        self.events.remove(ball);
+    }
+
+    // starts over with a grid whose cells fit the biggest ball and the number of balls and predicts all events again
+    fn rebuild(&mut self) {
+        for ball in 0..self.balls.len() {
+            self.move_ball(ball, self.time);
+        }
+        self.grid = Grid::new(self.width, self.height, &self.balls, &self.capsules, self.physics.arena == Arena::Periodic);
+        self.events = EventQueue::new(self.balls.len());
+        self.predict_all();
+    }
+
+    fn predict_all(&mut self) {
+        for ball in 0..self.balls.len() {
+            self.predict_walls(ball);
+            self.predict_obstacles(ball);
+            self.predict_cell(ball);
+            self.predict_neighbours(ball, |other| other > ball);
+        }
+    }
+
+    // moves the ball along its current path to the given time
+    fn move_ball(&mut self, ball: usize, time: f64) {
+        let interval = time - self.ball_times[ball];
+        self.ball_times[ball] = time;
+        let [axis_x, axis_y] = self.physics.axes();
+        let ball = &mut self.balls[ball];
+        let (dx, v_x) = axis_x.advance(ball.v_x, interval);
+        let (dy, v_y) = axis_y.advance(ball.v_y, interval);
+        ball.x += dx;
+        ball.y += dy;
+        ball.v_x = v_x;
+        ball.v_y = v_y;
+        ball.angle = (ball.angle + ball.omega * interval).rem_euclid(TAU);
+    }
+
+    // predicts all future events of a ball that just changed its course (skipping `except`, which was already predicted)
+    fn predict(&mut self, ball: usize, except: Option<usize>) {
+        self.predict_walls(ball);
+        self.predict_obstacles(ball);
+        self.predict_cell(ball);
+        self.predict_neighbours(ball, |other| Some(other) != except);
+    }
+
+    fn predict_walls(&mut self, ball: usize) {
+        if self.physics.arena == Arena::Periodic {
+            // balls go through its edges
+            return;
+        }
+        if let Some(ellipse) = self.physics.arena.ellipse(self.width, self.height) {
+            // with drag or for an ellipse, the search is continued step by step with events, like that of balls with quadratic drag
+            match ellipse.time_to_hit(&self.balls[ball], self.physics.axes(), 1) {
+                Some(CurvedCollision::At(time)) => self.events.push(self.ball_times[ball] + time, EventKind::Wall(ball, 0)),
+                Some(CurvedCollision::NotBefore(time)) => self.events.push(self.ball_times[ball] + time, EventKind::WallApproach(ball)),
+                None => {},
+            }
+            return;
+        }
+        for wall in 0..4 {
+            if let Some(time) = calculate_wall_collision(&self.balls, ball, wall, self.width, self.height, self.ball_times[ball], &self.physics) {
+                self.events.push(self.ball_times[ball] + time, EventKind::Wall(ball, wall));
+            }
+        }
+    }
+
+    fn predict_obstacles(&mut self, ball: usize) {
+        let mut capsules = std::mem::take(&mut self.neighbours);
+        self.grid.capsules(ball, &mut capsules);
+        for &capsule in &capsules {
+            self.predict_capsule(ball, capsule);
+        }
+        self.neighbours = capsules;
+    }
+
+    fn predict_capsule(&mut self, ball: usize, capsule: usize) {
+        // with drag or rotation, the search is continued step by step with events, like that of balls with quadratic drag
+        match self.capsules[capsule].time_to_hit(&self.balls[ball], self.physics.axes(), 1, self.ball_times[ball]) {
+            Some(CurvedCollision::At(time)) => self.events.push(self.ball_times[ball] + time, EventKind::Obstacle(ball, capsule)),
+            Some(CurvedCollision::NotBefore(time)) => self.events.push(self.ball_times[ball] + time, EventKind::ObstacleApproach(ball, capsule)),
+            None => {},
+        }
+    }
+
+    // predicts when the ball leaves its grid cell
+    fn predict_cell(&mut self, ball: usize) {
+        let [axis_x, axis_y] = self.physics.axes();
+        let crossing = self.grid.boundaries(ball).iter().enumerate()
+            .filter_map(|(boundary, position)| {
+                let position = (*position)?;
+                let upper = !boundary.is_multiple_of(2);
+                let time = if boundary < 2 {
+                    axis_x.time_to_reach(self.balls[ball].x, self.balls[ball].v_x, position, upper)
+                } else {
+                    axis_y.time_to_reach(self.balls[ball].y, self.balls[ball].v_y, position, upper)
+                }?;
+                Some((time, boundary))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((time, boundary)) = crossing {
+            self.events.push(self.ball_times[ball] + time, EventKind::Cell(ball, boundary));
+        }
+    }
+
+    fn predict_neighbours(&mut self, ball: usize, filter: impl Fn(usize) -> bool) {
+        let mut neighbours = std::mem::take(&mut self.neighbours);
+        self.grid.neighbours(ball, &mut neighbours);
+        for &other in &neighbours {
+            if filter(other) {
+                self.predict_balls(ball, other);
+            }
+        }
+        self.neighbours = neighbours;
+    }
+
+    fn predict_balls(&mut self, ball1: usize, ball2: usize) {
+        // both balls have to be at the same time for the prediction
+        let time = self.ball_times[ball1].max(self.ball_times[ball2]);
+        self.move_ball(ball1, time);
+        self.move_ball(ball2, time);
+        // in a periodic arena it is the copy of the second ball across the edges that is next to the first one,
+        // in a very small one there can be more of them
+        let mut earliest: Option<(f64, EventKind)> = None;
+        for shift in self.grid.shifts(ball1, ball2) {
+            let shifted;
+            let other = if shift == [0.0, 0.0] {
+                &self.balls[ball2]
+            } else {
+                shifted = self.balls[ball2].shifted(shift);
+                &shifted
+            };
+            let event = if self.physics.quadratic_drag > 0.0 {
+                // the search is continued step by step with events, usually one of the balls changes its course before it ends
+                match calculate_curved_collision(&self.balls[ball1], other, self.physics.axes(), 1) {
+                    Some(CurvedCollision::At(collision_time)) => Some((collision_time, EventKind::Balls(ball1, ball2))),
+                    Some(CurvedCollision::NotBefore(safe_time)) => Some((safe_time, EventKind::Approach(ball1, ball2))),
+                    None => None,
+                }
+            } else {
+                collision_time(&self.balls[ball1], other, &self.physics).map(|collision_time| (collision_time, EventKind::Balls(ball1, ball2)))
+            };
+            if let Some(event) = event.filter(|event| earliest.is_none_or(|earliest| event.0 < earliest.0)) {
+                earliest = Some(event);
+            }
+        }
+        if let Some((event_time, kind)) = earliest {
+            self.events.push(time + event_time, kind);
+        }
+    }
+}
+
+// collision of two touching balls, `restitution` is the share of their speed towards each other they keep,
+// but they always move apart with at least `min_speed`,
+// friction slows down the sliding of their surfaces against each other, making them spin
+fn collide_balls(balls: &mut [Ball], ball1: usize, ball2: usize, restitution: f64, min_speed: f64, friction: f64) {
+    let (dx, dy) = (balls[ball2].x - balls[ball1].x, balls[ball2].y - balls[ball1].y);
+    let d = (dx * dx + dy * dy).sqrt();
+    let nx = dx / d;
+    let ny = dy / d;
+    let speed = nx * (balls[ball1].v_x - balls[ball2].v_x) + ny * (balls[ball1].v_y - balls[ball2].v_y);
+    let p = (speed + (speed * restitution).max(min_speed)) / (balls[ball1].m + balls[ball2].m);
+    balls[ball1].v_x -= p * balls[ball2].m * nx;
+    balls[ball1].v_y -= p * balls[ball2].m * ny;
+    balls[ball2].v_x += p * balls[ball1].m * nx;
+    balls[ball2].v_y += p * balls[ball1].m * ny;
+
+    if friction > 0.0 {
+        // the speed at which the surface of the first ball slides along the second one at the point of contact
+        let (tx, ty) = (-ny, nx);
+        let sliding = tx * (balls[ball1].v_x - balls[ball2].v_x) + ty * (balls[ball1].v_y - balls[ball2].v_y)
+            + balls[ball1].omega * balls[ball1].r + balls[ball2].omega * balls[ball2].r;
+        let resistance = 1.0 / balls[ball1].m + 1.0 / balls[ball2].m
+            + balls[ball1].r * balls[ball1].r / balls[ball1].inertia + balls[ball2].r * balls[ball2].r / balls[ball2].inertia;
+        // the impulse that stops the sliding, unless it is more than friction allows
+        let max_impulse = friction * p * balls[ball1].m * balls[ball2].m;
+        let impulse = (sliding / resistance).clamp(-max_impulse, max_impulse);
+        for (ball, sign) in [(ball1, -1.0), (ball2, 1.0)] {
+            let ball = &mut balls[ball];
+            ball.v_x += sign * impulse / ball.m * tx;
+            ball.v_y += sign * impulse / ball.m * ty;
+            ball.omega -= impulse * ball.r / ball.inertia;
+        }
+    }
+}
+
+// collision of a ball with a wall (0: left, 1: right, 2: bottom, 3: top) moving with `wall_speed` along its axis,
+// works like a collision with a ball that doesn't move, in a frame moving with the wall
+fn collide_wall(ball: &mut Ball, wall: usize, wall_speed: f64, restitution: f64, min_speed: f64, friction: f64) {
+    let (speed, sliding_speed) = if wall < 2 { (&mut ball.v_x, &mut ball.v_y) } else { (&mut ball.v_y, &mut ball.v_x) };
+    let direction = if wall.is_multiple_of(2) { 1.0 } else { -1.0 };
+    // the speed into the wall, relative to it
+    let approach = (direction * (wall_speed - *speed)).max(0.0);
+    let rebound = (approach * restitution).max(min_speed);
+    let normal_impulse = ball.m * (rebound + approach);
+    *speed = wall_speed + direction * rebound;
+
+    if friction > 0.0 {
+        // along the wall, the point of contact moves with the ball's speed minus (or plus) its rotation
+        let direction = if wall == 0 || wall == 3 { -1.0 } else { 1.0 };
+        let sliding = *sliding_speed + direction * ball.omega * ball.r;
+        let resistance = 1.0 / ball.m + ball.r * ball.r / ball.inertia;
+        let max_impulse = friction * normal_impulse;
+        let impulse = (sliding / resistance).clamp(-max_impulse, max_impulse);
+        *sliding_speed -= impulse / ball.m;
+        ball.omega -= direction * impulse * ball.r / ball.inertia;
+    }
+}
+
+// collision of a ball with an obstacle or the edge of a round arena, `normal` points from the surface to the ball,
+// works like a collision with a wall, in a frame moving with the surface's `velocity` at the point of contact
+fn collide_surface(ball: &mut Ball, normal: [f64; 2], velocity: [f64; 2], restitution: f64, min_speed: f64, friction: f64) {
+    let [nx, ny] = normal;
+    let speed = -(nx * (ball.v_x - velocity[0]) + ny * (ball.v_y - velocity[1]));
+    let rebound = (speed * restitution).max(min_speed);
+    ball.v_x += (rebound + speed) * nx;
+    ball.v_y += (rebound + speed) * ny;
+
+    if friction > 0.0 {
+        // along the surface, the point of contact moves with the ball's speed minus its rotation, relative to the surface
+        let (tx, ty) = (-ny, nx);
+        let sliding = tx * (ball.v_x - velocity[0]) + ty * (ball.v_y - velocity[1]) - ball.omega * ball.r;
+        let resistance = 1.0 / ball.m + ball.r * ball.r / ball.inertia;
+        let max_impulse = friction * ball.m * (rebound + speed);
+        let impulse = (sliding / resistance).clamp(-max_impulse, max_impulse);
+        ball.v_x -= impulse / ball.m * tx;
+        ball.v_y -= impulse / ball.m * ty;
+        ball.omega += impulse * ball.r / ball.inertia;
+    }
+}
+
+// the speed at which two balls approach each other along the line between their centers
+fn approach_speed(balls: &[Ball], ball1: usize, ball2: usize) -> f64 {
+    let (dx, dy) = (balls[ball2].x - balls[ball1].x, balls[ball2].y - balls[ball1].y);
+    (dx * (balls[ball1].v_x - balls[ball2].v_x) + dy * (balls[ball1].v_y - balls[ball2].v_y)) / dx.hypot(dy)
+}
+
+// the fragments a ball hit from `direction` breaks into (None if they would be too small),
+// spinning around its center and moving away from it with `spread_speed`,
+// the direction of the hit is between two fragments, so that they don't stick out towards the other ball much
+fn break_ball(ball: &Ball, fragmentation: &Fragmentation, direction: f64, spread_speed: f64) -> Option<Vec<Ball>> {
+    let model = fragmentation.model;
+    let count = fragmentation.fragments as f64;
+    let r = model.radius(model.mass(ball.r) / count);
+    if r < fragmentation.min_radius {
+        return None;
+    }
+    let m = ball.m / count;
+    let inertia = model.inertia(m, r);
+    // the distance of the fragments from the center at which neighbouring ones touch
+    let distance = r / (PI / count).sin();
+    // spread around the center they have a bigger moment of inertia than the ball, so they spin slower
+    let omega = ball.inertia * ball.omega / (count * (inertia + m * distance * distance));
+
+    Some((0..fragmentation.fragments).map(|i| {
+        let (sin, cos) = (direction + PI * (2 * i + 1) as f64 / count).sin_cos();
+        let mut fragment = ball.clone();
+        fragment.x = ball.x + distance * cos;
+        fragment.y = ball.y + distance * sin;
+        fragment.m = m;
+        fragment.r = r;
+        fragment.v_x = ball.v_x + spread_speed * cos - omega * distance * sin;
+        fragment.v_y = ball.v_y + spread_speed * sin + omega * distance * cos;
+        fragment.inertia = inertia;
+        fragment.omega = omega;
+        fragment.charge = ball.charge / count;
+        fragment
+    }).collect())
+}
+
+// the ball that two touching balls merge into, in their center of mass and looking like the heavier one
+fn merge_balls(ball1: &Ball, ball2: &Ball, model: MassModel) -> Ball {
+    let m = ball1.m + ball2.m;
+    let x = (ball1.m * ball1.x + ball2.m * ball2.x) / m;
+    let y = (ball1.m * ball1.y + ball2.m * ball2.y) / m;
+    let v_x = (ball1.m * ball1.v_x + ball2.m * ball2.v_x) / m;
+    let v_y = (ball1.m * ball1.v_y + ball2.m * ball2.v_y) / m;
+    let r = model.radius(model.mass(ball1.r) + model.mass(ball2.r));
+    let inertia = model.inertia(m, r);
+    // the spins of both balls and their orbits around the center of mass
+    let angular_momentum: f64 = [ball1, ball2].iter()
+        .map(|ball| ball.inertia * ball.omega + ball.m * ((ball.x - x) * (ball.v_y - v_y) - (ball.y - y) * (ball.v_x - v_x)))
+        .sum();
+
+    let mut merged = if ball1.m >= ball2.m { ball1.clone() } else { ball2.clone() };
+    merged.x = x;
+    merged.y = y;
+    merged.m = m;
+    merged.r = r;
+    merged.v_x = v_x;
+    merged.v_y = v_y;
+    merged.inertia = inertia;
+    merged.omega = angular_momentum / inertia;
+    merged.charge = ball1.charge + ball2.charge;
+    merged
+}
+
+/// Returns the time after which `ball1` and `ball2` will collide, if they are approaching each other and ever do.
+/// In a periodic arena, the copy of `ball2` across the edges of the `width`×`height` box that is nearest to `ball1` is used.
+pub fn calculate_collision(balls: &[Ball], ball1: usize, ball2: usize, width: f64, height: f64, physics: &Physics) -> Option<f64> {
+    let (ball1, ball2) = (&balls[ball1], &balls[ball2]);
+    let shift = physics.arena.shift_towards([ball2.x, ball2.y], [ball1.x, ball1.y], width, height);
+    collision_time(ball1, &ball2.shifted(shift), physics)
+}
+
+// the time after which the balls collide (the copies of them across the edges of a periodic arena don't count)
+fn collision_time(ball1: &Ball, ball2: &Ball, physics: &Physics) -> Option<f64> {
+    if physics.quadratic_drag > 0.0 {
+        return match calculate_curved_collision(ball1, ball2, physics.axes(), 1000)? {
+            CurvedCollision::At(time) => Some(time),
+            CurvedCollision::NotBefore(_) => None,
+        };
+    }
+    // gravity accelerates both balls equally and linear drag slows them down equally,
+    // so they move in straight lines relative to each other, with the relative velocity decaying exponentially
+    let straight_time = calculate_straight_collision(ball1, ball2)?;
+    let k = physics.linear_drag;
+    if k == 0.0 {
+        Some(straight_time)
+    } else {
+        // the decaying relative velocity only takes them as far as the initial one would in 1/k
+        (k * straight_time < 1.0).then(|| -(-k * straight_time).ln_1p() / k)
+    }
+}
+
+// returns how long the balls would take to collide if they kept their relative velocity
+fn calculate_straight_collision(ball1: &Ball, ball2: &Ball) -> Option<f64> {
+    // write position of balls as functions of time (x + vx*t, y + vy*t)
+    // write distance of 2 balls with those functions
+    // square to get rid of square root
+    // find when that distance^2 function reaches d^2 for the first time
+
+    let d_pow2 = (ball1.r + ball2.r).powi(2); // distance between balls at collision squared (d^2)
+    let delta_x = ball1.x - ball2.x; // x1 - x2
+    let delta_y = ball1.y - ball2.y; // y1 - y2
+    let delta_vx = ball1.v_x - ball2.v_x; // vx1 - vx2
+    let delta_vy = ball1.v_y - ball2.v_y; // vy1 - vy2
+
+    // calculate coefficients of distance^2 function
+    let a = delta_vx.powi(2) + delta_vy.powi(2); // first coefficient
+    let b_divis_2 = (delta_x * delta_vx) + (delta_y * delta_vy);  // second coefficient divided by 2 (it simplifies function when in that form)
+    let c = delta_x.powi(2) + delta_y.powi(2);  // third coefficient
+
+    if b_divis_2 >= 0.0 { // the balls aren't approaching each other (or aren't moving), therefore, there is no collision
+        return None;
+    }
+    let discriminant = b_divis_2.powi(2) - (a * (c - d_pow2));
+    if discriminant < 0.0 { // minimum value of distance^2 function is bigger than d^2, the balls miss each other
+        return None;
+    }
+    // the smaller solution of the function, when its value is d^2,
+    // (-b/2 - sqrt(D)) / a written in a form that doesn't lose precision when a is small
+    let time = (c - d_pow2) / (- b_divis_2 + discriminant.sqrt());
+    Some(time.max(0.0))  // if they already touch, they collide right away
+}
+
+// the result of a search for a collision along curved paths
+pub(crate) enum CurvedCollision {
+    At(f64),
+    // the search was stopped, but they surely don't collide before this
+    NotBefore(f64),
+}
+
+// the collision time of a ball with a surface (the edge of a round arena or an obstacle) when it can't be calculated exactly,
+// found by repeatedly moving the ball as far as it can surely go without touching the surface:
+// `surface` gives the gap between them, the direction from the surface towards the ball and the velocity of the surface
+// where the ball would touch it, wherever the ball is after some time, and `step` how long it can surely move on
+// with a gap (at least `tolerance`) that changes with a speed, if the surface is `still`, the search ends once the ball can't get to it
+pub(crate) fn search_collision(ball: &Ball, axes: [Axis; 2], max_steps: usize, tolerance: f64, still: bool,
+    surface: impl Fn(&Ball, f64) -> (f64, [f64; 2], [f64; 2]), step: impl Fn(&Ball, f64, f64) -> f64) -> Option<CurvedCollision> {
+    // how far the ball can get before it stops (infinity if it doesn't)
+    let reach = axes[0].max_distance(ball.v_x).hypot(axes[1].max_distance(ball.v_y));
+    let mut moved = ball.clone();
+    let mut time = 0.0;
+    for _ in 0..max_steps {
+        let (dx, v_x) = axes[0].advance(ball.v_x, time);
+        let (dy, v_y) = axes[1].advance(ball.v_y, time);
+        (moved.x, moved.y, moved.v_x, moved.v_y) = (ball.x + dx, ball.y + dy, v_x, v_y);
+
+        let (gap, normal, velocity) = surface(&moved, time);
+        let gap_speed = normal[0] * (v_x - velocity[0]) + normal[1] * (v_y - velocity[1]);
+        if gap <= tolerance && gap_speed < 0.0 {
+            return Some(CurvedCollision::At(time));
+        }
+        if still && (gap > reach - dx.hypot(dy) || (axes.iter().all(|axis| axis.acceleration == 0.0) && v_x == 0.0 && v_y == 0.0)) {
+            // it stops before getting there
+            return None;
+        }
+
+        time += step(&moved, gap.max(tolerance), gap_speed);
+        if !time.is_finite() {
+            return None;
+        }
+    }
+    Some(CurvedCollision::NotBefore(time))
+}
+
+// collision time of balls whose paths are curved by quadratic drag, found by repeatedly moving them
+// as far as they can surely go without touching:
+// along each axis the velocities of both balls approach the same terminal velocity without overtaking each other,
+// so their relative speed never increases and it changes at most as fast as the drag changes their velocities
+fn calculate_curved_collision(ball1: &Ball, ball2: &Ball, axes: [Axis; 2], max_steps: usize) -> Option<CurvedCollision> {
+    let d = ball1.r + ball2.r;
+    let tolerance = d * 1e-9;
+    let mut time = 0.0;
+    for _ in 0..max_steps {
+        let (x1, v_x1) = axes[0].advance(ball1.v_x, time);
+        let (y1, v_y1) = axes[1].advance(ball1.v_y, time);
+        let (x2, v_x2) = axes[0].advance(ball2.v_x, time);
+        let (y2, v_y2) = axes[1].advance(ball2.v_y, time);
+        let (delta_x, delta_y) = (ball1.x + x1 - ball2.x - x2, ball1.y + y1 - ball2.y - y2);
+        let (delta_vx, delta_vy) = (v_x1 - v_x2, v_y1 - v_y2);
+
+        let distance = delta_x.hypot(delta_y);
+        let gap = distance - d;
+        let gap_speed = (delta_x * delta_vx + delta_y * delta_vy) / distance;
+        if gap <= tolerance && gap_speed < 0.0 {
+            return Some(CurvedCollision::At(time));
+        }
+        if delta_x * delta_vx >= 0.0 && delta_y * delta_vy >= 0.0 {
+            // moving apart along both axes, which never changes
+            return None;
+        }
+
+        // the gap can't shrink faster than gap + gap_speed*t - max_acceleration/2*t^2,
+        // where the acceleration comes from the drag changing the relative velocity and from its direction turning
+        let relative_speed = delta_vx.hypot(delta_vy);
+        let drag = axes.iter().zip([v_x1.abs().max(v_x2.abs()), v_y1.abs().max(v_y2.abs())])
+            .map(|(axis, speed)| axis.linear_drag + 2.0 * axis.quadratic_drag * speed.max(axis.terminal_speed().abs()))
+            .fold(0.0, f64::max);
+        let max_acceleration = relative_speed * (drag + relative_speed / d);
+        let gap = gap.max(tolerance);
+        time += 2.0 * gap / (-gap_speed + (gap_speed * gap_speed + 2.0 * max_acceleration * gap).sqrt());
+        if !time.is_finite() {
+            return None;
+        }
+    }
+    Some(CurvedCollision::NotBefore(time))
+}
+
+/// Returns the time after which `ball` will hit `wall` (0: left, 1: right, 2: bottom, 3: top), if it is moving or accelerating towards it,
+/// starting at `time` (when the walls are where [Physics::moving_walls] moved them by then).
+/// A round arena has a single wall (0) and a periodic one has none, there is no collision with a wall that doesn't exist.
+pub fn calculate_wall_collision(balls: &[Ball], ball: usize, wall: usize, width: f64, height: f64, time: f64, physics: &Physics) -> Option<f64> {
+    let [axis_x, axis_y] = physics.axes();
+    let ball = &balls[ball];
+    if physics.arena == Arena::Periodic {
+        return None;
+    }
+    if let Some(ellipse) = physics.arena.ellipse(width, height) {
+        return match ellipse.time_to_hit(ball, physics.axes(), 1000)? {
+            CurvedCollision::At(time) if wall == 0 => Some(time),
+            _ => None,
+        };
+    }
+    let walls = physics.walls(width, height, time);
+    let (axis, position, speed, target, upper) = match wall {
+        0 => (axis_x, ball.x, ball.v_x, walls[0] + ball.r, false), // left
+        1 => (axis_x, ball.x, ball.v_x, walls[1] - ball.r, true), // right
+        2 => (axis_y, ball.y, ball.v_y, walls[2] + ball.r, false), // bottom
+        3 => (axis_y, ball.y, ball.v_y, walls[3] - ball.r, true), // top
+        _ => return None,
+    };
+    let (wall_speed, moving_time) = physics.wall_motion(wall, time);
+    if moving_time > 0.0 {
+        if let Some(time) = axis.time_to_meet(position, speed, target, wall_speed, upper).filter(|&time| time < moving_time) {
+            return Some(time);
+        }
+        // the wall stops before it meets the ball, and stays there
+        let (distance, speed) = axis.advance(speed, moving_time);
+        return axis.time_to_reach(position + distance, speed, target + wall_speed * moving_time, upper).map(|time| moving_time + time);
+    }
+    axis.time_to_reach(position, speed, target, upper)
+}
+
+/// Returns the time after which `ball` will hit `obstacle`, if it ever does,
+/// starting at `time` (when a rotating obstacle is where [Obstacle::at] turned it by then).
+pub fn calculate_obstacle_collision(balls: &[Ball], ball: usize, obstacle: &Obstacle, time: f64, physics: &Physics) -> Option<f64> {
+    let axes = physics.axes();
+    obstacle.capsules().iter()
+        .filter_map(|capsule| {
+            // the search for a rotating obstacle only starts once the ball gets into the disc it sweeps, so it is continued from there
+            let mut moved = balls[ball].clone();
+            let mut elapsed = 0.0;
+            for _ in 0..2 {
+                match capsule.time_to_hit(&moved, axes, 1000, time + elapsed)? {
+                    CurvedCollision::At(collision) => return Some(elapsed + collision),
+                    CurvedCollision::NotBefore(later) => {
+                        let (dx, v_x) = axes[0].advance(moved.v_x, later);
+                        let (dy, v_y) = axes[1].advance(moved.v_y, later);
+                        (moved.x, moved.y, moved.v_x, moved.v_y) = (moved.x + dx, moved.y + dy, v_x, v_y);
+                        elapsed += later;
+                    },
+                }
+            }
+            None
+        })
+        .min_by(f64::total_cmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::obstacle::Shape;
+    use crate::setup::BallSettings;
+
+    fn balls(arena: Arena, num_of_balls: usize, seed: u64) -> Vec<Ball> {
+        let settings = BallSettings { num_of_balls, radius_min: 5.0, radius_max: 15.0, speed_min: 20.0, speed_max: 300.0, ..BallSettings::default() };
+        settings.generate_in(arena, 800.0, 600.0, &mut ChaCha8Rng::seed_from_u64(seed)).unwrap()
+    }
+
+    fn energy(balls: &[Ball]) -> f64 {
+        balls.iter().map(|ball| 0.5 * ball.m * (ball.v_x * ball.v_x + ball.v_y * ball.v_y) + 0.5 * ball.inertia * ball.omega * ball.omega).sum()
+    }
+
+    fn momentum(balls: &[Ball]) -> [f64; 2] {
+        balls.iter().fold([0.0, 0.0], |[x, y], ball| [x + ball.m * ball.v_x, y + ball.m * ball.v_y])
+    }
+
+    // checks that no two balls overlap by more than rounding errors, that all balls are inside the arena (between the walls)
+    // and that no ball overlaps an obstacle
+    fn assert_valid(simulation: &Simulation) {
+        let (physics, width, height) = (simulation.physics(), simulation.width(), simulation.height());
+        let balls = simulation.balls();
+        for (i, ball) in balls.iter().enumerate() {
+            let shrunk = Ball { r: ball.r - 1e-6, ..ball.clone() };
+            assert!(physics.contains(&shrunk, width, height, simulation.time()), "ball {i} left the arena at {}", simulation.time());
+            for (j, other) in balls[..i].iter().enumerate() {
+                assert!(!physics.arena.balls_overlap(&shrunk, other, width, height), "balls {i} and {j} overlap at {}", simulation.time());
+            }
+            for obstacle in simulation.current_obstacles() {
+                assert!(!physics.arena.overlaps_obstacle(&shrunk, &obstacle, width, height), "ball {i} overlaps an obstacle at {}", simulation.time());
+            }
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance * expected.abs().max(1.0), "{actual} != {expected}");
+    }
+
+    #[test]
+    fn elastic_collisions_conserve_energy() {
+        for arena in [Arena::Rectangle, Arena::Circle, Arena::Ellipse] {
+            let physics = Physics { arena, ..Physics::default() };
+            let mut simulation = Simulation::with_physics(800.0, 600.0, balls(arena, 150, 1), physics);
+            let start = energy(simulation.balls());
+            for _ in 0..120 {
+                simulation.step(1.0 / 60.0);
+                assert_valid(&simulation);
+            }
+            assert_close(energy(simulation.balls()), start, 1e-9);
+        }
+    }
+
+    #[test]
+    fn elastic_collisions_conserve_momentum_in_a_periodic_arena() {
+        let physics = Physics { arena: Arena::Periodic, ..Physics::default() };
+        let mut simulation = Simulation::with_physics(800.0, 600.0, balls(Arena::Periodic, 200, 2), physics);
+        let (start_energy, start_momentum) = (energy(simulation.balls()), momentum(simulation.balls()));
+        for _ in 0..120 {
+            simulation.step(1.0 / 60.0);
+            assert_valid(&simulation);
+        }
+        assert_close(energy(simulation.balls()), start_energy, 1e-9);
+        let scale = simulation.balls().iter().map(|ball| ball.m * ball.v_x.hypot(ball.v_y)).sum::<f64>();
+        for (end, start) in momentum(simulation.balls()).into_iter().zip(start_momentum) {
+            assert!((end - start).abs() <= 1e-9 * scale, "{end} != {start}");
+        }
+    }
+
+    #[test]
+    fn ignores_walls_that_dont_exist() {
+        let ball = [Ball::new(400.0, 300.0, 1.0, 10.0, 100.0, 100.0, [0; 3])];
+        let arena = |arena| Physics { arena, ..Physics::default() };
+        assert_close(calculate_wall_collision(&ball, 0, 1, 800.0, 600.0, 0.0, &Physics::default()).unwrap(), 389.0 / 100.0, 1e-12);
+        assert_eq!(calculate_wall_collision(&ball, 0, 4, 800.0, 600.0, 0.0, &Physics::default()), None);
+        assert!(calculate_wall_collision(&ball, 0, 0, 800.0, 600.0, 0.0, &arena(Arena::Circle)).is_some());
+        assert_eq!(calculate_wall_collision(&ball, 0, 1, 800.0, 600.0, 0.0, &arena(Arena::Circle)), None);
+        assert_eq!(calculate_wall_collision(&ball, 0, 0, 800.0, 600.0, 0.0, &arena(Arena::Periodic)), None);
+    }
+
+    #[test]
+    fn meets_moving_walls() {
+        let walls = |offsets, speeds, duration| Physics { moving_walls: Some(MovingWalls { offsets, speeds, duration }), ..Physics::default() };
+        let ball = [Ball::new(400.0, 300.0, 1.0, 10.0, 0.0, 50.0, [0; 3])];
+        // the top wall moves down from 599 with 100 pixels per second
+        let physics = walls([0.0; 4], [0.0, 0.0, 0.0, 100.0], 5.0);
+        assert_close(calculate_wall_collision(&ball, 0, 3, 800.0, 600.0, 0.0, &physics).unwrap(), 289.0 / 150.0, 1e-12);
+        // a second later it is 100 pixels lower
+        assert_close(calculate_wall_collision(&ball, 0, 3, 800.0, 600.0, 1.0, &physics).unwrap(), 189.0 / 150.0, 1e-12);
+        // the wall stops at 499 before it meets the ball
+        let physics = walls([0.0; 4], [0.0, 0.0, 0.0, 100.0], 1.0);
+        assert_close(calculate_wall_collision(&ball, 0, 3, 800.0, 600.0, 0.0, &physics).unwrap(), 1.0 + 139.0 / 50.0, 1e-12);
+
+        // the left wall starts 100 pixels inside and moves out with 50 pixels per second for 2 seconds
+        let ball = [Ball::new(300.0, 300.0, 1.0, 10.0, -100.0, 0.0, [0; 3])];
+        let physics = walls([100.0, 0.0, 0.0, 0.0], [-50.0, 0.0, 0.0, 0.0], 2.0);
+        assert_close(calculate_wall_collision(&ball, 0, 0, 800.0, 600.0, 0.0, &physics).unwrap(), 2.9, 1e-12);
+        // a ball moving away from the wall that moves after it
+        let ball = [Ball::new(300.0, 300.0, 1.0, 10.0, 20.0, 0.0, [0; 3])];
+        let physics = walls([100.0, 0.0, 0.0, 0.0], [80.0, 0.0, 0.0, 0.0], 5.0);
+        assert_close(calculate_wall_collision(&ball, 0, 0, 800.0, 600.0, 0.0, &physics).unwrap(), 190.0 / 60.0, 1e-12);
+        assert_eq!(calculate_wall_collision(&ball, 0, 0, 800.0, 600.0, 5.0, &physics), None);
+    }
+
+    #[test]
+    fn moving_walls_push_balls() {
+        // the top wall moving down with 100 pixels per second hits a ball moving up with 50
+        let mut ball = Ball::new(400.0, 300.0, 1.0, 10.0, 0.0, 50.0, [0; 3]);
+        collide_wall(&mut ball, 3, -100.0, 1.0, 0.0, 0.0);
+        assert_eq!(ball.v_y, -250.0);
+        let mut ball = Ball::new(400.0, 300.0, 1.0, 10.0, 0.0, 50.0, [0; 3]);
+        collide_wall(&mut ball, 3, -100.0, 0.5, 0.0, 0.0);
+        assert_eq!(ball.v_y, -175.0);
+        // the left wall moving out with 50 pixels per second hit by a ball moving with 100
+        let mut ball = Ball::new(400.0, 300.0, 1.0, 10.0, -100.0, 30.0, [0; 3]);
+        collide_wall(&mut ball, 0, -50.0, 1.0, 0.0, 0.0);
+        assert_eq!((ball.v_x, ball.v_y), (0.0, 30.0));
+
+        // a piston pushing into an elastic gas heats it up, without balls getting past it
+        let physics = Physics { moving_walls: Some(MovingWalls { offsets: [0.0; 4], speeds: [0.0, 0.0, 0.0, 100.0], duration: 2.0 }), ..Physics::default() };
+        let mut simulation = Simulation::with_physics(800.0, 600.0, balls(Arena::Rectangle, 60, 4), physics);
+        let mut energy_before = energy(simulation.balls());
+        for _ in 0..120 {
+            simulation.step(1.0 / 60.0);
+            assert_valid(&simulation);
+            let energy = energy(simulation.balls());
+            assert!(energy >= energy_before * (1.0 - 1e-12));
+            energy_before = energy;
+        }
+        assert!(energy_before > 1.01 * energy(&balls(Arena::Rectangle, 60, 4)));
+    }
+
+    #[test]
+    fn balls_stay_out_of_rotating_obstacles() {
+        let obstacles = vec![
+            Obstacle { shape: Shape::Segment { start: [150.0, 300.0], end: [350.0, 300.0], width: 10.0 }, color: [0; 3], rotation: Some(Rotation { pivot: [250.0, 300.0], angular_velocity: 3.0 }) },
+            Obstacle { shape: Shape::Polygon { points: vec![[500.0, 250.0], [620.0, 250.0], [620.0, 370.0]] }, color: [0; 3], rotation: Some(Rotation { pivot: [580.0, 330.0], angular_velocity: -2.0 }) },
+        ];
+        let balls = balls(Arena::Rectangle, 150, 3).into_iter().filter(|ball| obstacles.iter().all(|obstacle| obstacle.swept_disc().is_none_or(|(center, radius)| (ball.x - center[0]).hypot(ball.y - center[1]) > radius + ball.r))).collect();
+        let physics = Physics { gravity: [0.0, -200.0], restitution: 0.9, wall_restitution: 0.9, wall_friction: 0.3, ..Physics::default() };
+        let mut simulation = Simulation::with_obstacles(800.0, 600.0, balls, obstacles, physics);
+        for _ in 0..120 {
+            simulation.step(1.0 / 60.0);
+            assert_valid(&simulation);
+        }
+    }
+
+    #[test]
+    fn dropped_balls_come_to_rest() {
+        let physics = Physics { gravity: [0.0, -500.0], wall_restitution: 0.5, ..Physics::default() };
+        let mut simulation = Simulation::with_physics(800.0, 600.0, vec![Ball::new(400.0, 300.0, 1.0, 10.0, 0.0, 0.0, [0; 3])], physics.clone());
+        // balls bounce at most once per contact time, so steps of a quarter of it see every bounce
+        let mut bounces = 0;
+        let mut last_bounce = simulation.collision_times[0];
+        for _ in 0..3200 {
+            simulation.step(physics.contact_time / 4.0);
+            if simulation.collision_times[0] != last_bounce {
+                bounces += 1;
+                last_bounce = simulation.collision_times[0];
+            }
+        }
+        assert!(bounces <= 800, "{bounces} bounces");
+        // it keeps bouncing invisibly with half of gravity times the contact time
+        let ball = &simulation.balls()[0];
+        assert!(ball.y - ball.r < 500.0 * physics.contact_time * physics.contact_time, "{}", ball.y);
+        assert!(ball.v_y.abs() <= 500.0 * physics.contact_time / 2.0 + 1e-9, "{}", ball.v_y);
+    }
+
+    #[test]
+    fn inelastic_collisions_without_gravity_lose_energy() {
+        let physics = Physics { restitution: 0.9, wall_restitution: 0.9, ..Physics::default() };
+        let mut simulation = Simulation::with_physics(800.0, 600.0, balls(Arena::Rectangle, 100, 5), physics);
+        let mut collisions = 0;
+        for _ in 0..2000 {
+            let collision_times = simulation.collision_times.clone();
+            let energy_before = energy(simulation.balls());
+            simulation.step(0.0005);
+            // even balls that collide again right away lose energy
+            if simulation.collision_times != collision_times {
+                collisions += 1;
+                assert!(energy(simulation.balls()) < energy_before, "no energy lost at {}", simulation.time());
+            }
+        }
+        assert!(collisions > 100);
+    }
+
+    #[test]
+    fn merging_conserves_mass_and_momentum() {
+        for arena in [Arena::Periodic, Arena::Rectangle, Arena::Ellipse] {
+            let balls = balls(arena, 150, 6);
+            let (mass, momentum_before): (f64, _) = (balls.iter().map(|ball| ball.m).sum(), momentum(&balls));
+            // the scale of the rounding errors
+            let scale: f64 = balls.iter().map(|ball| ball.m * ball.v_x.hypot(ball.v_y)).sum();
+            let physics = Physics { arena, merging: Some(MassModel::Circle), ..Physics::default() };
+            let mut simulation = Simulation::with_physics(800.0, 600.0, balls, physics);
+            for _ in 0..120 {
+                simulation.step(1.0 / 60.0);
+                assert_valid(&simulation);
+                assert_close(simulation.balls().iter().map(|ball| ball.m).sum(), mass, 1e-12);
+                // walls change the momentum, but not in a periodic arena
+                let momentum = momentum(simulation.balls());
+                if arena == Arena::Periodic {
+                    assert!((momentum[0] - momentum_before[0]).hypot(momentum[1] - momentum_before[1]) < 1e-12 * scale, "{momentum:?} != {momentum_before:?}");
+                }
+            }
+            assert!(simulation.balls().len() < 100, "{} balls left in {arena:?}", simulation.balls().len());
+        }
+    }
+
+    #[test]
+    fn fragmentation_conserves_mass_and_momentum() {
+        for arena in [Arena::Periodic, Arena::Rectangle, Arena::Circle] {
+            let balls = balls(arena, 40, 8);
+            let (mass, momentum_before): (f64, _) = (balls.iter().map(|ball| ball.m).sum(), momentum(&balls));
+            let scale: f64 = balls.iter().map(|ball| ball.m * ball.v_x.hypot(ball.v_y)).sum();
+            let fragmentation = Fragmentation { speed: 150.0, min_radius: 2.0, ..Fragmentation::default() };
+            let physics = Physics { arena, fragmentation: Some(fragmentation), ..Physics::default() };
+            let mut simulation = Simulation::with_physics(800.0, 600.0, balls, physics);
+            for _ in 0..60 {
+                simulation.step(1.0 / 60.0);
+                // fragments never overlap the balls around them or the walls
+                assert_valid(&simulation);
+                assert_close(simulation.balls().iter().map(|ball| ball.m).sum(), mass, 1e-12);
+                let momentum = momentum(simulation.balls());
+                if arena == Arena::Periodic {
+                    assert!((momentum[0] - momentum_before[0]).hypot(momentum[1] - momentum_before[1]) < 1e-12 * scale, "{momentum:?} != {momentum_before:?}");
+                }
+            }
+            assert!(simulation.balls().len() > 60, "{} balls in {arena:?}", simulation.balls().len());
+        }
+    }
+
+    #[test]
+    fn runs_the_same_from_the_same_seed() {
+        let physics = Physics { gravity: [0.0, -300.0], restitution: 0.95, friction: 0.2, wall_friction: 0.1, ..Physics::default() };
+        let run = |seed| {
+            let mut simulation = Simulation::with_physics(800.0, 600.0, balls(Arena::Rectangle, 80, seed), physics.clone());
+            for _ in 0..120 {
+                simulation.step(1.0 / 60.0);
+            }
+            simulation.into_balls()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn friction_never_reverses_sliding() {
+        use rand::Rng;
+
+        let mut ball_rng = ChaCha8Rng::seed_from_u64(9);
+        let mut random_ball = |x: f64, y: f64, r: f64| Ball {
+            omega: ball_rng.gen_range(-20.0..20.0),
+            ..Ball::new(x, y, ball_rng.gen_range(0.1..10.0), r, ball_rng.gen_range(-200.0..200.0), ball_rng.gen_range(-200.0..200.0), [0; 3])
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(10);
+        for _ in 0..1000 {
+            let (friction, restitution) = (rng.gen_range(0.0..2.0), rng.gen_range(0.0..=1.0));
+            let (r1, r2, direction) = (rng.gen_range(1.0..30.0), rng.gen_range(1.0..30.0), rng.gen_range(0.0..2.0 * PI));
+            let (nx, ny) = (direction.cos(), direction.sin());
+
+            // the speed at which the surfaces slide along each other at the point of contact, as seen from the first ball
+            let mut balls = vec![random_ball(0.0, 0.0, r1), random_ball((r1 + r2) * nx, (r1 + r2) * ny, r2)];
+            let sliding = |balls: &[Ball]| -ny * (balls[0].v_x - balls[1].v_x) + nx * (balls[0].v_y - balls[1].v_y) + balls[0].omega * balls[0].r + balls[1].omega * balls[1].r;
+            if approach_speed(&balls, 0, 1) > 0.0 {
+                let before = sliding(&balls);
+                collide_balls(&mut balls, 0, 1, restitution, 0.0, friction);
+                let after = sliding(&balls);
+                assert!(after * before >= -1e-9 * before.abs() && after.abs() <= before.abs() * (1.0 + 1e-12), "{before} -> {after}");
+            }
+
+            // along the wall, and along a surface with the normal pointing from it to the ball
+            let wall = rng.gen_range(0..4);
+            let mut ball = random_ball(100.0, 100.0, r1);
+            let wall_sliding = |ball: &Ball| if wall < 2 { ball.v_y } else { ball.v_x } + if wall == 0 || wall == 3 { -1.0 } else { 1.0 } * ball.omega * ball.r;
+            let before = wall_sliding(&ball);
+            collide_wall(&mut ball, wall, 0.0, restitution, 0.0, friction);
+            let after = wall_sliding(&ball);
+            assert!(after * before >= -1e-9 * before.abs() && after.abs() <= before.abs() * (1.0 + 1e-12), "wall {wall}: {before} -> {after}");
+
+            let mut ball = random_ball(100.0, 100.0, r1);
+            let velocity = [rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0)];
+            let surface_sliding = |ball: &Ball| -ny * (ball.v_x - velocity[0]) + nx * (ball.v_y - velocity[1]) - ball.omega * ball.r;
+            if nx * (ball.v_x - velocity[0]) + ny * (ball.v_y - velocity[1]) < 0.0 {
+                let before = surface_sliding(&ball);
+                collide_surface(&mut ball, [nx, ny], velocity, restitution, 0.0, friction);
+                let after = surface_sliding(&ball);
+                assert!(after * before >= -1e-9 * before.abs() && after.abs() <= before.abs() * (1.0 + 1e-12), "{before} -> {after}");
+            }
+        }
+    }
+
+    #[test]
+    fn balls_dont_spin_without_friction() {
+        let obstacles = vec![Obstacle { shape: Shape::Polygon { points: vec![[350.0, 250.0], [450.0, 250.0], [400.0, 340.0]] }, color: [0; 3], rotation: None }];
+        for arena in [Arena::Rectangle, Arena::Ellipse] {
+            let balls = balls(arena, 60, 11).into_iter().filter(|ball| !obstacles[0].overlaps(ball)).collect();
+            let physics = Physics { gravity: [0.0, -300.0], restitution: 0.8, wall_restitution: 0.9, arena, ..Physics::default() };
+            let mut simulation = Simulation::with_obstacles(800.0, 600.0, balls, obstacles.clone(), physics);
+            for _ in 0..120 {
+                simulation.step(1.0 / 60.0);
+            }
+            assert!(simulation.balls().iter().all(|ball| ball.omega == 0.0 && ball.angle == 0.0));
+        }
+    }
+}